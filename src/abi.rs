@@ -0,0 +1,26 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+/*
+ * Calling convention:
+ *
+ *   r0        return value, caller-saved
+ *   r1..r3    arguments, caller-saved
+ *   r4..r14   callee-saved
+ *   r15       stack pointer, callee-saved
+ */
+pub const RETURN_VALUE: usize = 0;
+pub const ARGUMENTS: [usize; 3] = [1, 2, 3];
+pub const FIRST_CALLEE_SAVED: usize = 4;
+
+/// Registers `RETS` restores from the snapshot saved by `CALLS`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RetsRestores {
+    /// All registers, so a subroutine cannot return values in registers.
+    #[default]
+    All,
+    /// Only the callee-saved registers, leaving the return value and arguments as the subroutine left them.
+    CalleeSaved,
+}
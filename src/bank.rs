@@ -0,0 +1,74 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use bus::Device;
+
+/// Selects the bank mapped into the bank window.
+pub const BANK_SELECT: u16 = 0xef20;
+/// Number of banks (read only).
+pub const BANK_COUNT: u16 = 0xef21;
+
+/// Memory larger than the address space, seen one bank at a time through a window.
+///
+/// The controller is attached to the bus twice: its registers at `BANK_SELECT..=BANK_COUNT`
+/// and its window at `window..=last_addr()`.
+pub struct BankedMemory {
+    pub window: u16,
+    pub page_size: usize,
+    pub bank: usize,
+    pub cells: Vec<i16>,
+}
+
+impl BankedMemory {
+    pub fn new(window: u16, page_size: usize, banks: usize) -> Self {
+        BankedMemory {
+            window,
+            page_size,
+            bank: 0,
+            cells: vec![0; page_size * banks],
+        }
+    }
+
+    pub fn banks(&self) -> usize {
+        self.cells.len() / self.page_size
+    }
+
+    /// Last address of the bank window.
+    pub fn last_addr(&self) -> u16 {
+        self.window + (self.page_size - 1) as u16
+    }
+
+    pub fn in_window(&self, addr: u16) -> bool {
+        addr >= self.window && addr <= self.last_addr()
+    }
+
+    /// The cells of `bank`, regardless of which bank is selected.
+    pub fn page(&self, bank: usize) -> &[i16] {
+        &self.cells[bank * self.page_size..(bank + 1) * self.page_size]
+    }
+}
+
+impl Device for BankedMemory {
+    fn read(&mut self, addr: u16) -> i16 {
+        match addr {
+            BANK_SELECT => self.bank as i16,
+            BANK_COUNT => self.banks() as i16,
+            _ => self.cells[self.bank * self.page_size + (addr - self.window) as usize],
+        }
+    }
+
+    /// Selecting a bank that does not exist leaves the selection unchanged.
+    fn write(&mut self, addr: u16, value: i16) {
+        match addr {
+            BANK_SELECT => {
+                if (value as u16 as usize) < self.banks() {
+                    self.bank = value as u16 as usize;
+                }
+            },
+            BANK_COUNT => (),
+            _ => self.cells[self.bank * self.page_size + (addr - self.window) as usize] = value,
+        }
+    }
+}
@@ -1,4 +1,4 @@
-/**
+/*
  * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
  * All rights reserved.
  */
@@ -22,24 +22,43 @@ use regex::Regex;
   <hexdigit> := <digit> | 'a' | 'b' | 'c' | 'd' | 'e' | 'f'
   <Ident>  := <alpha>+ (<alpha> | <digit> | '_')*
   <Label>  := <Ident>
-  <Opcode> := 'cp' | 'add' | 'sub' | 'mul' | 'div' | 'xor' | 'and' | 'or' | 'not'
-            | 'shr' | 'shl' | 'cmp'
+  <Opcode> := 'cp' | 'mv' | 'xchg' | 'add' | 'sub' | 'mul' | 'div' | 'xor' | 'and' | 'or' | 'not'
+            | 'adc' | 'sbc' | 'mulw' | 'divw' | 'mod'
+            | 'shr' | 'shl' | 'sar' | 'rol' | 'ror' | 'rcl' | 'rcr'
+            | 'bt' | 'bts' | 'btr' | 'btc' | 'popcnt' | 'clz' | 'bswap' | 'cmp'
             | 'be' | 'bne' | 'bg' | 'bge' | 'bl' | 'ble' | 'bc'
-            | 'jmp' | 'puts' | 'getc' | 'call' | 'ret' | 'halt'
+            | 'bnc' | 'bn' | 'bnn' | 'bv' | 'bnv' | 'ba' | 'bbe'
+            | 'bz' | 'bnz' | 'bb' | 'bae'
+            | 'jmp' | 'puts' | 'putc' | 'putn' | 'getc' | 'call' | 'ret' | 'calls' | 'rets' | 'push' | 'pop' | 'nop' | 'ei' | 'di' | 'iret' | 'wait'
+            | 'syscall' | 'sysret' | 'jmpu' | 'hostcall' | 'yield' | 'halt'
   <Register> := 'r' <digit>+
   <UnarySign> := '-' | '+'
   <DecNum> := '#' <UnarySign>? <digit>+
   <HexNum> := '$' <hexdigit>+
   <BinNum> := '!' (0 | 1)+
+  <Number> := <DecNum> | <HexNum> | <BinNum>
+  <Indirect> := '[' <Register> ']'
+  <Indexed> := '[' <Register> ('+' | '-') <Number> ']'
+  <PostInc> := '[' <Register> '+' ']'
+  <MemRef> := '[' (<Number> | <Label>) ']'
+
+  <Proc>   := '.PROC' <Label> ('uses' <Register> (',' <Register>)*)?
+  <EndP>   := '.ENDP'
+
+  Between .PROC and .ENDP, the parser follows the procedure's label with a PUSH for each
+  used register, and precedes every RET with the matching POPs in reverse order.
+  There is no code generator yet, so no prologue or epilogue code is emitted; the
+  rewritten lines only appear in the debug log.
 */
 
-struct AST {
+#[derive(Debug)]
+struct Ast {
     label: Option<String>,
     opcode: Option<String>,
     operands: Option<Vec<String>>,
 }
 
-impl AST {
+impl Ast {
     pub fn new() -> Self {
         Self {
             label: Option::default(),
@@ -52,6 +71,8 @@ impl AST {
 pub struct Compiler {
     obj: Vec<u8>,
     labels: HashMap<String, usize>,
+    /// Registers saved by the enclosing `.PROC`, if any.
+    proc_uses: Option<Vec<String>>,
 }
 
 pub enum Token {
@@ -62,11 +83,18 @@ pub enum Token {
     Dat(Vec<i16>),
 }
 
+impl Default for Compiler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Compiler {
     pub fn new() -> Self {
         Compiler {
             obj: Vec::new(),
             labels: HashMap::new(),
+            proc_uses: None,
         }
     }
 
@@ -83,20 +111,89 @@ impl Compiler {
         Ok(matches)
     }
 
+    /// Expands `.PROC`/`.ENDP` blocks into the tokens of plain instruction lines.
+    fn expand(&mut self, tokens: Vec<String>, line_no: usize) -> Result<Vec<Vec<String>>, Error> {
+        let directive = tokens.first().map(|t| t.to_uppercase());
+        match directive.as_deref() {
+            Some(".PROC") => {
+                if self.proc_uses.is_some() {
+                    return Err(Error::SyntaxError(line_no, ".PROC inside .PROC".to_string()));
+                }
+                let name = match tokens.get(1) {
+                    Some(name) => name.clone(),
+                    None => return Err(Error::SyntaxError(line_no, ".PROC without a name".to_string())),
+                };
+                let uses: Vec<String> = match tokens.get(2) {
+                    Some(t) if t.eq_ignore_ascii_case("uses") => tokens[3..].join("")
+                        .split(',')
+                        .filter(|r| !r.is_empty())
+                        .map(|r| r.to_string())
+                        .collect(),
+                    Some(t) => return Err(Error::SyntaxError(line_no, format!("expected 'uses', found '{}'", t))),
+                    None => Vec::new(),
+                };
+                let mut lines = vec![vec![format!("{}:", name)]];
+                lines.extend(uses.iter().map(|r| vec!["push".to_string(), r.clone()]));
+                self.proc_uses = Some(uses);
+                Ok(lines)
+            },
+            Some(".ENDP") => match self.proc_uses.take() {
+                Some(_) => Ok(Vec::new()),
+                None => Err(Error::SyntaxError(line_no, ".ENDP without .PROC".to_string())),
+            },
+            _ => {
+                let uses = match &self.proc_uses {
+                    Some(uses) if !uses.is_empty() => uses,
+                    _ => return Ok(vec![tokens]),
+                };
+                let has_label = tokens.first().is_some_and(|t| t.ends_with(':'));
+                let opcode = tokens.get(if has_label { 1 } else { 0 });
+                if !opcode.is_some_and(|op| op.eq_ignore_ascii_case("ret")) {
+                    return Ok(vec![tokens]);
+                }
+                let mut lines: Vec<Vec<String>> = uses.iter().rev().map(|r| vec!["pop".to_string(), r.clone()]).collect();
+                if has_label {
+                    lines[0].insert(0, tokens[0].clone());
+                    lines.push(tokens[1..].to_vec());
+                }
+                else {
+                    lines.push(tokens);
+                }
+                Ok(lines)
+            },
+        }
+    }
+
     fn parse<B: BufRead>(&mut self, code: &mut Lines<B>) -> Result<(), Error> {
-        for (_line_no, line) in code.enumerate() {
+        let mut last_line_no = 0;
+        for (line_no, line) in code.enumerate() {
             if let Ok(line) = line {
-                let mut ast = AST::new();
+                last_line_no = line_no + 1;
                 /*
                  * LABEL OPCODE OPERANDS ; COMMENT
                  * .DIRECTIVE
                  */
-                let tokens = self.tokenize(line);
-                for token in tokens.iter().peekable() {
-
+                let tokens = self.tokenize(line)?;
+                for tokens in self.expand(tokens, line_no + 1)? {
+                    let mut ast = Ast::new();
+                    let mut tokens = tokens.into_iter().peekable();
+                    if let Some(label) = tokens.next_if(|t| t.ends_with(':')) {
+                        let label = label.trim_end_matches(':').to_string();
+                        self.labels.insert(label.clone(), self.obj.len());
+                        ast.label = Some(label);
+                    }
+                    ast.opcode = tokens.next();
+                    let operands: Vec<String> = tokens.collect();
+                    if !operands.is_empty() {
+                        ast.operands = Some(operands);
+                    }
+                    log::debug!("{}: {:?}", line_no + 1, ast);
                 }
             }
         }
+        if self.proc_uses.is_some() {
+            return Err(Error::SyntaxError(last_line_no, ".PROC without .ENDP".to_string()));
+        }
         Ok(())
     }
 
@@ -115,8 +212,43 @@ fn main() {
     let args: Vec<String> = env::args().collect();
     let filename = &args[1];
     let mut compiler = Compiler::new();
-    match compiler.assemble(&filename) {
+    match compiler.assemble(filename) {
         Ok(()) => (),
         Err(e) => panic!("{}", e),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Compiler;
+
+    fn line(tokens: &[&str]) -> Vec<String> {
+        tokens.iter().map(|t| t.to_string()).collect()
+    }
+
+    #[test]
+    fn expand_proc() {
+        let mut compiler = Compiler::new();
+        let lines = [
+            line(&[".PROC", "max", "uses", "r4,", "r5"]),
+            line(&["cp", "r0,", "r4"]),
+            line(&["done:", "ret"]),
+            line(&[".ENDP"]),
+        ];
+        let expanded: Vec<Vec<Vec<String>>> = lines.iter().enumerate()
+            .map(|(no, tokens)| compiler.expand(tokens.clone(), no + 1).unwrap())
+            .collect();
+        assert_eq!(expanded[0], vec![line(&["max:"]), line(&["push", "r4"]), line(&["push", "r5"])]);
+        assert_eq!(expanded[1], vec![line(&["cp", "r0,", "r4"])]);
+        assert_eq!(expanded[2], vec![line(&["done:", "pop", "r5"]), line(&["pop", "r4"]), line(&["ret"])]);
+        assert!(expanded[3].is_empty());
+    }
+
+    #[test]
+    fn expand_unbalanced() {
+        let mut compiler = Compiler::new();
+        assert!(compiler.expand(line(&[".ENDP"]), 1).is_err());
+        compiler.expand(line(&[".PROC", "f"]), 1).unwrap();
+        assert!(compiler.expand(line(&[".PROC", "g"]), 2).is_err());
+    }
+}
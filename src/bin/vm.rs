@@ -1,19 +1,131 @@
-/**
+/*
  * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
  * All rights reserved.
  */
 
+extern crate riscvm;
+
+use riscvm::console::ScriptConsole;
+use riscvm::error::Error;
+use riscvm::exception::ErrorPolicy;
+use riscvm::memory::MemoryModel;
+use riscvm::task::{Exit, Scheduler};
+use riscvm::{Machine, MachineBuilder};
 use std::env;
+use std::fs;
+use std::io::{self, Write};
+
+/// Runs the machine, redrawing the terminal whenever the screen has changed.
+fn run_with_screen(vm: &mut Machine) -> Result<(), Error> {
+    print!("\x1b[2J");
+    loop {
+        let running = vm.step()?;
+        let mut screen = vm.screen.borrow_mut();
+        if screen.dirty {
+            print!("{}", screen.to_ansi());
+            let _ = io::stdout().flush();
+            screen.dirty = false;
+        }
+        if !running {
+            return Ok(());
+        }
+    }
+}
+
+/// Runs each object as a task and prints each task's output and exit status.
+fn run_tasks(vm: Machine, obj_filenames: &[String]) {
+    let mut scheduler = Scheduler::new(vm);
+    for obj_filename in obj_filenames {
+        if let Err(e) = scheduler.load(obj_filename) {
+            panic!("{}: {}", obj_filename, e);
+        }
+    }
+    scheduler.run();
+    for (obj_filename, task) in obj_filenames.iter().zip(&scheduler.tasks) {
+        print!("{}", task.output());
+        match &task.exit {
+            Some(Exit::Halted(r0)) => eprintln!("{}: halted with r0 = {}", obj_filename, r0),
+            Some(Exit::Failed(e)) => eprintln!("{}: {}", obj_filename, e),
+            None => (),
+        }
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    let obj_filename = &args[1];
-    let mut vm = riscvm::Machine::new();
-    match vm.load(&obj_filename) {
-        Ok(()) => match vm.run() {
-            Ok(()) => (),
+    let mut args = env::args().skip(1);
+    let mut input_filename: Option<String> = None;
+    let mut obj_filenames: Vec<String> = Vec::new();
+    let mut show_screen = false;
+    let mut headless = false;
+    let mut html = false;
+    let mut trap = false;
+    let mut von_neumann = false;
+    let mut mmu = false;
+    let mut dump_filename: Option<String> = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--input" => input_filename = args.next(),
+            "--screen" => show_screen = true,
+            "--headless" => headless = true,
+            "--html" => html = true,
+            "--trap" => trap = true,
+            "--von-neumann" => von_neumann = true,
+            "--mmu" => mmu = true,
+            "--dump-screen" => dump_filename = args.next(),
+            _ => obj_filenames.push(arg),
+        }
+    }
+    if obj_filenames.is_empty() {
+        panic!("usage: vm [--input FILE] [--screen | --headless] [--html] [--trap] [--von-neumann] [--mmu] [--dump-screen FILE] OBJECT...");
+    }
+    let mut builder = MachineBuilder::new();
+    if trap {
+        builder = builder.error_policy(ErrorPolicy::Trap);
+    }
+    if von_neumann {
+        builder = builder.memory_model(MemoryModel::VonNeumann);
+    }
+    if mmu {
+        builder = builder.mmu();
+    }
+    if let Some(input_filename) = input_filename {
+        match ScriptConsole::open(&input_filename) {
+            Ok(console) => builder = builder.console(Box::new(console)),
             Err(e) => panic!("{}", e),
-        },
+        }
+    }
+    let mut vm = match builder.build() {
+        Ok(vm) => vm,
         Err(e) => panic!("{}", e),
+    };
+    if obj_filenames.len() > 1 {
+        run_tasks(vm, &obj_filenames);
+        return;
+    }
+    if let Err(e) = vm.load(&obj_filenames[0]) {
+        panic!("{}", e);
+    }
+    let result = if show_screen && !headless {
+        run_with_screen(&mut vm)
+    }
+    else {
+        vm.run()
+    };
+    if let Err(e) = result {
+        panic!("{}", e);
+    }
+    let dump = if html {
+        vm.screen.borrow().to_html()
+    }
+    else {
+        vm.screen_text().join("\n") + "\n"
+    };
+    if headless {
+        print!("{}", dump);
+    }
+    if let Some(dump_filename) = dump_filename {
+        if let Err(e) = fs::write(&dump_filename, dump) {
+            panic!("cannot write {}: {}", dump_filename, e);
+        }
     }
 }
@@ -0,0 +1,228 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use abi::RetsRestores;
+use bank::{BankedMemory, BANK_COUNT, BANK_SELECT};
+use bus::{Bus, Ram};
+use console::{Console, StdConsole};
+use error::Error;
+use exception::ErrorPolicy;
+use flags::Flags;
+use memory::MemoryModel;
+use mmu::{Mmu, MMU_CONTROL, MMU_FAULT_STATUS};
+use privilege::Mode;
+use protection::{Permissions, Protection};
+use screen::{Screen, SCREEN_ATTR, SCREEN_BASE, SCREEN_CURSOR_X};
+use timer::{Timer, TIMER_COUNT, TIMER_PERIOD};
+use {Machine, IVT_BASE, SP, STACK_TOP};
+
+/// The whole 16-bit data address space.
+pub const DEFAULT_MEMORY_SIZE: usize = 0x10000;
+pub const DEFAULT_STACK_DEPTH: usize = 1000;
+pub const DEFAULT_SCREEN_WIDTH: usize = 80;
+pub const DEFAULT_SCREEN_HEIGHT: usize = 24;
+
+/// Configures and creates a `Machine`.
+pub struct MachineBuilder {
+    memory_size: usize,
+    stack_depth: usize,
+    stack_limit: u16,
+    screen_width: usize,
+    screen_height: usize,
+    console: Box<dyn Console>,
+    error_policy: ErrorPolicy,
+    rets_restores: RetsRestores,
+    /// Bank window address, page size and number of banks.
+    banks: Option<(u16, usize, usize)>,
+    protection: Protection,
+    code_window: Option<u16>,
+    memory_model: MemoryModel,
+    mmu: bool,
+}
+
+impl Default for MachineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MachineBuilder {
+    pub fn new() -> Self {
+        MachineBuilder {
+            memory_size: DEFAULT_MEMORY_SIZE,
+            stack_depth: DEFAULT_STACK_DEPTH,
+            stack_limit: 0x0000,
+            screen_width: DEFAULT_SCREEN_WIDTH,
+            screen_height: DEFAULT_SCREEN_HEIGHT,
+            console: Box::new(StdConsole),
+            error_policy: ErrorPolicy::default(),
+            rets_restores: RetsRestores::default(),
+            banks: None,
+            protection: Protection::default(),
+            code_window: None,
+            memory_model: MemoryModel::default(),
+            mmu: false,
+        }
+    }
+
+    /// Number of RAM cells, or bytes in the von Neumann model, mapped from address 0;
+    /// at most `DEFAULT_MEMORY_SIZE`. Devices shadow the RAM at their addresses.
+    pub fn memory_size(mut self, cells: usize) -> Self {
+        self.memory_size = cells;
+        self
+    }
+
+    /// Number of register snapshots `CALLS` and interrupt entry can save.
+    pub fn stack_depth(mut self, depth: usize) -> Self {
+        self.stack_depth = depth;
+        self
+    }
+
+    /// Lowest address the guest stack may grow to; by default the stack may fill the memory below it.
+    pub fn stack_limit(mut self, addr: u16) -> Self {
+        self.stack_limit = addr;
+        self
+    }
+
+    /// Screen geometry; the screen window must fit between `SCREEN_BASE` and `IVT_BASE`.
+    pub fn screen_size(mut self, width: usize, height: usize) -> Self {
+        self.screen_width = width;
+        self.screen_height = height;
+        self
+    }
+
+    pub fn console(mut self, console: Box<dyn Console>) -> Self {
+        self.console = console;
+        self
+    }
+
+    pub fn error_policy(mut self, policy: ErrorPolicy) -> Self {
+        self.error_policy = policy;
+        self
+    }
+
+    pub fn rets_restores(mut self, restores: RetsRestores) -> Self {
+        self.rets_restores = restores;
+        self
+    }
+
+    /// Adds `banks` banks of `page_size` cells each, seen one at a time through a window
+    /// starting at `window`, which must end below the I/O page at $EF00.
+    pub fn banked_memory(mut self, window: u16, page_size: usize, banks: usize) -> Self {
+        self.banks = Some((window, page_size, banks));
+        self
+    }
+
+    /// Restricts the accesses allowed to `first..=last`. Execute permission applies to the pc.
+    pub fn protect(mut self, first: u16, last: u16, permissions: Permissions) -> Self {
+        self.protection.add(first, last, permissions);
+        self
+    }
+
+    /// Makes the code readable and writable through memory operands, one byte per cell,
+    /// starting at data address `base`.
+    pub fn code_window(mut self, base: u16) -> Self {
+        self.code_window = Some(base);
+        self
+    }
+
+    pub fn memory_model(mut self, model: MemoryModel) -> Self {
+        self.memory_model = model;
+        self
+    }
+
+    /// Adds an MMU, initially disabled, with its registers at `MMU_CONTROL..=MMU_FAULT_STATUS`.
+    pub fn mmu(mut self) -> Self {
+        self.mmu = true;
+        self
+    }
+
+    pub fn build(self) -> Result<Machine, Error> {
+        if self.memory_size == 0 || self.memory_size > DEFAULT_MEMORY_SIZE {
+            return Err(Error::InvalidConfiguration(format!("memory size {} outside of 1..={}", self.memory_size, DEFAULT_MEMORY_SIZE)));
+        }
+        let screen_cells = self.screen_width * self.screen_height;
+        if screen_cells == 0 || screen_cells > (IVT_BASE - SCREEN_BASE) as usize {
+            return Err(Error::InvalidConfiguration(format!("screen size {}x{} does not fit the screen window", self.screen_width, self.screen_height)));
+        }
+        if self.memory_model == MemoryModel::VonNeumann && self.code_window.is_some() {
+            return Err(Error::InvalidConfiguration("the von Neumann model has no separate code window".to_string()));
+        }
+        let screen = Rc::new(RefCell::new(Screen::new(self.screen_width, self.screen_height)));
+        let mut bus = Bus::default();
+        let (ram, code) = match self.memory_model {
+            MemoryModel::Harvard => {
+                let ram = Rc::new(RefCell::new(Ram::new(0x0000, self.memory_size)));
+                bus.attach(0x0000, (self.memory_size - 1) as u16, ram.clone());
+                (Some(ram), Vec::new())
+            },
+            MemoryModel::VonNeumann => (None, vec![0; self.memory_size]),
+        };
+        bus.attach(SCREEN_CURSOR_X, SCREEN_ATTR, screen.clone());
+        let last = screen.borrow().last_addr();
+        bus.attach(SCREEN_BASE, last, screen.clone());
+        let timer = Rc::new(RefCell::new(Timer::default()));
+        bus.attach(TIMER_PERIOD, TIMER_COUNT, timer.clone());
+        let banked = match self.banks {
+            Some((window, page_size, banks)) => {
+                if page_size == 0 || window as usize + page_size > SCREEN_CURSOR_X as usize {
+                    return Err(Error::InvalidConfiguration(format!("bank window of {} cells at 0x{:04x} overlaps the I/O page", page_size, window)));
+                }
+                if banks == 0 || banks > u16::MAX as usize {
+                    return Err(Error::InvalidConfiguration(format!("{} banks outside of 1..={}", banks, u16::MAX)));
+                }
+                let banked = Rc::new(RefCell::new(BankedMemory::new(window, page_size, banks)));
+                bus.attach(BANK_SELECT, BANK_COUNT, banked.clone());
+                let last = banked.borrow().last_addr();
+                bus.attach(window, last, banked.clone());
+                Some(banked)
+            },
+            None => None,
+        };
+        let mmu = if self.mmu {
+            let mmu = Rc::new(RefCell::new(Mmu::default()));
+            bus.attach(MMU_CONTROL, MMU_FAULT_STATUS, mmu.clone());
+            Some(mmu)
+        }
+        else {
+            None
+        };
+        let stack_top = self.memory_size.min(STACK_TOP as usize) as u16;
+        let mut r = [0x0000; 16];
+        r[SP] = stack_top as i16;
+        Ok(Machine {
+            pc: 0x0000,
+            r,
+            flags: Flags::default(),
+            mode: Mode::default(),
+            bus,
+            ram,
+            code,
+            memory_model: self.memory_model,
+            screen,
+            timer,
+            banked,
+            mmu,
+            stack: Vec::new(),
+            stack_depth: self.stack_depth,
+            rets_restores: self.rets_restores,
+            stack_top,
+            stack_limit: self.stack_limit,
+            console: self.console,
+            interrupts_enabled: false,
+            pending_irqs: 0,
+            waiting: false,
+            yielded: false,
+            error_policy: self.error_policy,
+            protection: self.protection,
+            code_window: self.code_window,
+            host_fns: HashMap::new(),
+        })
+    }
+}
@@ -0,0 +1,115 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A peripheral attached to the data address space.
+///
+/// Devices get the full 16-bit address of the accessed cell, so a device attached to
+/// several address ranges can tell them apart.
+pub trait Device {
+    fn read(&mut self, addr: u16) -> i16;
+
+    fn write(&mut self, addr: u16, value: i16);
+
+    /// Called once after every step.
+    fn tick(&mut self) {}
+
+    /// Takes back a pending interrupt request, returning its interrupt line.
+    fn interrupt(&mut self) -> Option<u8> {
+        None
+    }
+}
+
+/// Plain read/write memory.
+pub struct Ram {
+    pub base: u16,
+    pub cells: Vec<i16>,
+}
+
+impl Ram {
+    pub fn new(base: u16, size: usize) -> Self {
+        Ram {
+            base,
+            cells: vec![0; size],
+        }
+    }
+}
+
+impl Device for Ram {
+    fn read(&mut self, addr: u16) -> i16 {
+        self.cells[(addr - self.base) as usize]
+    }
+
+    fn write(&mut self, addr: u16, value: i16) {
+        self.cells[(addr - self.base) as usize] = value;
+    }
+}
+
+struct Mapping {
+    first: u16,
+    last: u16,
+    device: Rc<RefCell<dyn Device>>,
+}
+
+/// Decodes data addresses and forwards accesses to the attached devices.
+#[derive(Default)]
+pub struct Bus {
+    mappings: Vec<Mapping>,
+}
+
+impl Bus {
+    /// Maps the addresses `first..=last` to `device`.
+    /// Ranges attached later take precedence over overlapping ranges attached earlier.
+    pub fn attach(&mut self, first: u16, last: u16, device: Rc<RefCell<dyn Device>>) {
+        self.mappings.push(Mapping { first, last, device });
+    }
+
+    fn find(&self, addr: u16) -> Option<&Mapping> {
+        self.mappings.iter().rev().find(|m| m.first <= addr && addr <= m.last)
+    }
+
+    /// Reads a cell, or returns `None` if no device is mapped at `addr`.
+    pub fn read(&self, addr: u16) -> Option<i16> {
+        self.find(addr).map(|m| m.device.borrow_mut().read(addr))
+    }
+
+    /// Writes a cell, or returns `false` if no device is mapped at `addr`.
+    pub fn write(&self, addr: u16, value: i16) -> bool {
+        match self.find(addr) {
+            Some(m) => {
+                m.device.borrow_mut().write(addr, value);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Every attached device once, even if it is mapped to several ranges.
+    fn devices(&self) -> impl Iterator<Item = &Rc<RefCell<dyn Device>>> {
+        self.mappings.iter().enumerate()
+            .filter(move |(i, m)| self.mappings[..*i].iter().all(|other| !Rc::ptr_eq(&other.device, &m.device)))
+            .map(|(_, m)| &m.device)
+    }
+
+    pub fn tick(&self) {
+        for device in self.devices() {
+            device.borrow_mut().tick();
+        }
+    }
+
+    /// Collects the pending interrupt requests of all devices as a bit mask of interrupt lines;
+    /// requests on lines beyond the mask are ignored.
+    pub fn interrupts(&self) -> u16 {
+        let mut pending = 0;
+        for device in self.devices() {
+            if let Some(irq) = device.borrow_mut().interrupt() {
+                pending |= 1u16.checked_shl(irq as u32).unwrap_or(0);
+            }
+        }
+        pending
+    }
+}
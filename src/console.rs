@@ -0,0 +1,139 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::rc::Rc;
+
+use error::Error;
+
+/// Character I/O used by `PUTC`, `PUTN`, `PUTS` and `GETC`.
+pub trait Console {
+    /// Writes a single character.
+    fn putc(&mut self, c: u8);
+
+    /// Writes a string of characters.
+    fn puts(&mut self, s: &[u8]) {
+        for c in s {
+            self.putc(*c);
+        }
+    }
+
+    /// Reads a single character, or `None` at the end of the input.
+    fn getc(&mut self) -> Option<u8>;
+
+    /// Tells whether `getc` can return a character right away.
+    /// Consoles that cannot tell without blocking never report input as ready.
+    fn input_ready(&mut self) -> bool {
+        false
+    }
+}
+
+/// Console connected to the standard input and output of the host process.
+#[derive(Default)]
+pub struct StdConsole;
+
+impl Console for StdConsole {
+    fn putc(&mut self, c: u8) {
+        self.puts(&[c]);
+    }
+
+    fn puts(&mut self, s: &[u8]) {
+        let mut out = io::stdout();
+        let _ = out.write_all(s);
+        let _ = out.flush();
+    }
+
+    fn getc(&mut self) -> Option<u8> {
+        let mut buf = [0u8; 1];
+        match io::stdin().read(&mut buf) {
+            Ok(1) => Some(buf[0]),
+            _ => None,
+        }
+    }
+}
+
+/// Console reading from and writing to memory buffers.
+///
+/// Clones share the same buffers, so a clone kept by the caller can inspect
+/// the output after the machine has run.
+#[derive(Clone, Default)]
+pub struct BufferConsole {
+    input: Rc<RefCell<VecDeque<u8>>>,
+    output: Rc<RefCell<Vec<u8>>>,
+}
+
+impl BufferConsole {
+    pub fn new(input: &[u8]) -> Self {
+        let console = BufferConsole::default();
+        console.feed(input);
+        console
+    }
+
+    /// Appends characters to the pending input.
+    pub fn feed(&self, input: &[u8]) {
+        self.input.borrow_mut().extend(input);
+    }
+
+    pub fn output(&self) -> Vec<u8> {
+        self.output.borrow().clone()
+    }
+
+    pub fn output_string(&self) -> String {
+        String::from_utf8_lossy(&self.output.borrow()).to_string()
+    }
+}
+
+impl Console for BufferConsole {
+    fn putc(&mut self, c: u8) {
+        self.output.borrow_mut().push(c);
+    }
+
+    fn getc(&mut self) -> Option<u8> {
+        self.input.borrow_mut().pop_front()
+    }
+
+    fn input_ready(&mut self) -> bool {
+        !self.input.borrow().is_empty()
+    }
+}
+
+/// Console taking its input from a file and writing to the standard output.
+pub struct ScriptConsole {
+    input: VecDeque<u8>,
+    output: StdConsole,
+}
+
+impl ScriptConsole {
+    pub fn open(filename: &str) -> Result<Self, Error> {
+        match fs::read(filename) {
+            Ok(input) => Ok(ScriptConsole {
+                input: input.into(),
+                output: StdConsole,
+            }),
+            Err(e) => Err(Error::FileNotFound(e.to_string())),
+        }
+    }
+}
+
+impl Console for ScriptConsole {
+    fn putc(&mut self, c: u8) {
+        self.output.putc(c);
+    }
+
+    fn puts(&mut self, s: &[u8]) {
+        self.output.puts(s);
+    }
+
+    fn getc(&mut self) -> Option<u8> {
+        self.input.pop_front()
+    }
+
+    fn input_ready(&mut self) -> bool {
+        !self.input.is_empty()
+    }
+}
@@ -1,11 +1,13 @@
-/**
+/*
  * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
  * All rights reserved.
  */
 
- extern crate thiserror;
+extern crate thiserror;
 use self::thiserror::Error;
 
+use protection::Access;
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("division by zero")]
@@ -22,6 +24,28 @@ pub enum Error {
     StackOverflow,
     #[error("stack underflow")]
     StackUnderflow,
+    #[error("jump target 0x{0:04x} outside of code @ 0x{1:04x}")]
+    InvalidJumpTarget(usize, usize),
+    #[error("no memory at address 0x{0:04x} @ 0x{1:04x}")]
+    InvalidAddress(u16, usize),
+    #[error("{kind} protection fault at address 0x{addr:04x} @ 0x{pc:04x}")]
+    ProtectionFault { addr: u16, pc: usize, kind: Access },
+    #[error("privileged instruction 0x{0:02x} in user mode @ 0x{1:04x}")]
+    PrivilegedInstruction(u8, usize),
+    #[error("SYSCALL {0} without a handler @ 0x{1:04x}")]
+    UnhandledSyscall(u16, usize),
+    #[error("HOSTCALL {0} without a registered host function @ 0x{1:04x}")]
+    UnknownHostFunction(u16, usize),
+    #[error("{kind} page fault at address 0x{addr:04x} @ 0x{pc:04x}")]
+    PageFault { addr: u16, pc: usize, kind: Access },
+    #[error("no interrupt line {0}")]
+    InvalidInterruptLine(u8),
+    #[error("WAIT with interrupts disabled @ 0x{0:04x}")]
+    WaitWithInterruptsDisabled(usize),
     #[error("invalid character '{0}'")]
     InvalidCharacter(char),
+    #[error("invalid machine configuration: {0}")]
+    InvalidConfiguration(String),
+    #[error("line {0}: {1}")]
+    SyntaxError(usize, String),
 }
@@ -0,0 +1,48 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use error::Error;
+
+/// Exception vector table; `Machine::exception_vector` gives the address of the handler address for a cause.
+pub const EVT_BASE: u16 = 0xffe0;
+
+pub const EXC_DIVISION_BY_ZERO: u16 = 0;
+pub const EXC_STACK_OVERFLOW: u16 = 1;
+pub const EXC_STACK_UNDERFLOW: u16 = 2;
+pub const EXC_ILLEGAL_OPCODE: u16 = 3;
+pub const EXC_INVALID_ADDRESS: u16 = 4;
+pub const EXC_INVALID_JUMP_TARGET: u16 = 5;
+pub const EXC_PROTECTION_FAULT: u16 = 6;
+/// Not an error: the entry of the handler `SYSCALL` enters.
+pub const EXC_SYSCALL: u16 = 7;
+/// A page is not present or denies the access; `MMU_FAULT_STATUS` tells which access failed
+/// and `MMU_FAULT_ADDR` holds the faulting virtual address.
+pub const EXC_PAGE_FAULT: u16 = 8;
+
+/// What the machine does when an instruction fails.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// Stop and return the error to the host.
+    #[default]
+    Fatal,
+    /// Continue at the guest's exception handler if it installed one, with the cause in r0
+    /// and the address of the faulting instruction in r1.
+    Trap,
+}
+
+/// The exception cause a guest handler sees for `e`, or `None` if `e` is always fatal.
+pub fn cause(e: &Error) -> Option<u16> {
+    match e {
+        Error::DivisionByZero => Some(EXC_DIVISION_BY_ZERO),
+        Error::StackOverflow => Some(EXC_STACK_OVERFLOW),
+        Error::StackUnderflow => Some(EXC_STACK_UNDERFLOW),
+        Error::UnknownOpcode(..) => Some(EXC_ILLEGAL_OPCODE),
+        Error::InvalidAddress(..) => Some(EXC_INVALID_ADDRESS),
+        Error::PageFault { .. } => Some(EXC_PAGE_FAULT),
+        Error::InvalidJumpTarget(..) => Some(EXC_INVALID_JUMP_TARGET),
+        Error::ProtectionFault { .. } | Error::PrivilegedInstruction(..) => Some(EXC_PROTECTION_FAULT),
+        _ => None,
+    }
+}
@@ -0,0 +1,188 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+/// The status flags, updated by every ALU instruction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Flags {
+    /// zero: the result is 0
+    pub z: bool,
+    /// negative: bit 15 of the result is set
+    pub n: bool,
+    /// carry: unsigned carry out of bit 15, or borrow into it on subtraction
+    pub c: bool,
+    /// overflow: the signed result does not fit into 16 bits
+    pub v: bool,
+}
+
+impl Flags {
+    pub fn from_result(value: i16, c: bool, v: bool) -> Self {
+        Flags {
+            z: value == 0,
+            n: value < 0,
+            c,
+            v,
+        }
+    }
+
+    /// Flags for results of bitwise operations, which clear carry and overflow.
+    pub fn logic(value: i16) -> Self {
+        Flags::from_result(value, false, false)
+    }
+
+    pub fn add(a: i16, b: i16) -> (i16, Self) {
+        let (_, c) = (a as u16).overflowing_add(b as u16);
+        let (result, v) = a.overflowing_add(b);
+        (result, Flags::from_result(result, c, v))
+    }
+
+    pub fn sub(a: i16, b: i16) -> (i16, Self) {
+        let (_, c) = (a as u16).overflowing_sub(b as u16);
+        let (result, v) = a.overflowing_sub(b);
+        (result, Flags::from_result(result, c, v))
+    }
+
+    /// Carry and overflow are both set if the signed product does not fit into 16 bits.
+    pub fn mul(a: i16, b: i16) -> (i16, Self) {
+        let (result, v) = a.overflowing_mul(b);
+        (result, Flags::from_result(result, v, v))
+    }
+
+    /// The divisor must not be 0. `i16::MIN / -1` yields `i16::MIN` with overflow set.
+    pub fn div(a: i16, b: i16) -> (i16, Self) {
+        let (result, v) = a.overflowing_div(b);
+        (result, Flags::from_result(result, false, v))
+    }
+
+    /// Adds `b` and the incoming carry to `a`.
+    pub fn adc(a: i16, b: i16, carry: bool) -> (i16, Self) {
+        let sum = a as u16 as u32 + b as u16 as u32 + carry as u32;
+        let signed = a as i32 + b as i32 + carry as i32;
+        let result = sum as u16 as i16;
+        (result, Flags::from_result(result, sum > 0xffff, signed != result as i32))
+    }
+
+    /// Subtracts `b` and the incoming borrow from `a`.
+    pub fn sbc(a: i16, b: i16, borrow: bool) -> (i16, Self) {
+        let c = (a as u16 as u32) < b as u16 as u32 + borrow as u32;
+        let signed = a as i32 - b as i32 - borrow as i32;
+        let result = signed as i16;
+        (result, Flags::from_result(result, c, signed != result as i32))
+    }
+
+    /// Remainder with the sign of the dividend. The divisor must not be 0.
+    pub fn rem(a: i16, b: i16) -> (i16, Self) {
+        let (result, v) = a.overflowing_rem(b);
+        (result, Flags::from_result(result, false, v))
+    }
+
+    /// Signed 16x16 bit product, returned as (low word, high word).
+    /// Carry and overflow are set if the product does not fit into 16 bits.
+    pub fn mulw(a: i16, b: i16) -> (i16, i16, Self) {
+        let product = a as i32 * b as i32;
+        let fits = product == product as i16 as i32;
+        let flags = Flags {
+            z: product == 0,
+            n: product < 0,
+            c: !fits,
+            v: !fits,
+        };
+        (product as i16, (product >> 16) as i16, flags)
+    }
+
+    /// Divides the 32 bit value (`hi`:`lo`) by `b`, returned as (quotient, remainder).
+    /// Yields `None` if the quotient does not fit into 16 bits. The divisor must not be 0.
+    pub fn divw(hi: i16, lo: i16, b: i16) -> Option<(i16, i16, Self)> {
+        let dividend = ((hi as i32) << 16 | lo as u16 as i32) as i64;
+        let quotient = dividend / b as i64;
+        let remainder = dividend % b as i64;
+        if quotient != quotient as i16 as i64 {
+            return None;
+        }
+        let quotient = quotient as i16;
+        Some((quotient, remainder as i16, Flags::from_result(quotient, false, false)))
+    }
+
+    /// Logical shift left. The count is taken as an unsigned value, counts above 16 yield 0.
+    /// Carry receives the last bit shifted out.
+    pub fn shl(a: i16, count: i16) -> (i16, Self) {
+        let x = a as u16 as u32;
+        let (result, c) = match count as u16 {
+            0 => (x, false),
+            n @ 1..=16 => (x << n, (x >> (16 - n)) & 1 != 0),
+            _ => (0, false),
+        };
+        let result = result as u16 as i16;
+        (result, Flags::from_result(result, c, false))
+    }
+
+    /// Logical shift right. The count is taken as an unsigned value, counts above 16 yield 0.
+    /// Carry receives the last bit shifted out.
+    pub fn shr(a: i16, count: i16) -> (i16, Self) {
+        let x = a as u16 as u32;
+        let (result, c) = match count as u16 {
+            0 => (x, false),
+            n @ 1..=16 => (x >> n, (x >> (n - 1)) & 1 != 0),
+            _ => (0, false),
+        };
+        let result = result as u16 as i16;
+        (result, Flags::from_result(result, c, false))
+    }
+
+    /// Arithmetic shift right. The count is taken as an unsigned value, counts of 16 and above
+    /// fill the result with the sign bit. Carry receives the last bit shifted out.
+    pub fn sar(a: i16, count: i16) -> (i16, Self) {
+        let (result, c) = match count as u16 {
+            0 => (a, false),
+            n @ 1..=15 => (a >> n, (a >> (n - 1)) & 1 != 0),
+            _ => (a >> 15, a < 0),
+        };
+        (result, Flags::from_result(result, c, false))
+    }
+
+    /// Rotate left by the count modulo 16. Carry receives the bit rotated into bit 0.
+    pub fn rol(a: i16, count: i16) -> (i16, Self) {
+        let result = (a as u16).rotate_left((count as u16 % 16) as u32) as i16;
+        (result, Flags::from_result(result, count != 0 && result & 1 != 0, false))
+    }
+
+    /// Rotate right by the count modulo 16. Carry receives the bit rotated into bit 15.
+    pub fn ror(a: i16, count: i16) -> (i16, Self) {
+        let result = (a as u16).rotate_right((count as u16 % 16) as u32) as i16;
+        (result, Flags::from_result(result, count != 0 && result < 0, false))
+    }
+
+    /// Rotate left through carry, i.e. a 17 bit rotation by the count modulo 17.
+    pub fn rcl(a: i16, count: i16, carry: bool) -> (i16, Self) {
+        let x = (carry as u32) << 16 | a as u16 as u32;
+        let n = (count as u16 % 17) as u32;
+        let y = if n == 0 { x } else { (x << n | x >> (17 - n)) & 0x1ffff };
+        let result = y as u16 as i16;
+        (result, Flags::from_result(result, y >> 16 != 0, false))
+    }
+
+    /// Rotate right through carry, i.e. a 17 bit rotation by the count modulo 17.
+    pub fn rcr(a: i16, count: i16, carry: bool) -> (i16, Self) {
+        let x = (carry as u32) << 16 | a as u16 as u32;
+        let n = (count as u16 % 17) as u32;
+        let y = if n == 0 { x } else { (x >> n | x << (17 - n)) & 0x1ffff };
+        let result = y as u16 as i16;
+        (result, Flags::from_result(result, y >> 16 != 0, false))
+    }
+
+    /// Signed greater than, after `CMP a b`.
+    pub fn greater(&self) -> bool {
+        !self.z && self.n == self.v
+    }
+
+    /// Signed less than, after `CMP a b`.
+    pub fn less(&self) -> bool {
+        self.n != self.v
+    }
+
+    /// Unsigned greater than, after `CMP a b`.
+    pub fn above(&self) -> bool {
+        !self.c && !self.z
+    }
+}
@@ -1,70 +1,167 @@
-/**
+/*
  * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
  * All rights reserved.
  */
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fs::File;
+use std::mem;
 use std::io::Read;
-use std::convert::TryInto;
+use std::convert::TryFrom;
+use std::rc::Rc;
 
+pub mod abi;
+pub mod bank;
+pub mod builder;
+pub mod bus;
+pub mod console;
 pub mod error;
+pub mod exception;
+pub mod flags;
+pub mod memory;
+pub mod mmu;
 pub mod opcode;
+pub mod privilege;
+pub mod protection;
+pub mod screen;
+pub mod task;
+pub mod timer;
 
+use abi::{RetsRestores, FIRST_CALLEE_SAVED, RETURN_VALUE};
+use bank::BankedMemory;
+use bus::{Bus, Device, Ram};
+use console::Console;
 use error::Error;
+use exception::{ErrorPolicy, EVT_BASE, EXC_SYSCALL};
+use flags::Flags;
+use memory::MemoryModel;
+use mmu::{Mmu, FAULT_EXECUTE, FAULT_NONE, FAULT_READ, FAULT_WRITE, PAGE_SHIFT, PTE_PRESENT, PTE_USER, PTE_WRITABLE};
 use opcode::Opcode;
+use privilege::{Mode, SUPERVISOR_BASE};
+use protection::{Access, Protection};
+use screen::Screen;
+use timer::Timer;
 
-pub enum ComparisonResult {
-    None,
-    LessThan,
-    Equal,
-    GreaterThan,
-}
+pub use builder::MachineBuilder;
 
-const SCREEN_HEIGHT: usize = 24;
-const SCREEN_WIDTH: usize = 80;
-const STACK_SIZE: usize = 1000;
+/// Fixed by the instruction encoding, which has 4 bits per register operand.
 const MAX_REGISTERS: usize = 16;
 
+/// Register holding the stack pointer.
+pub const SP: usize = 15;
+/// Initial stack pointer; the stack grows downwards from the cell below it.
+pub const STACK_TOP: u16 = 0xef00;
+
+/// Interrupt vector table: the handler address for interrupt line n is stored in the n-th word from `IVT_BASE`.
+pub const IVT_BASE: u16 = 0xffd0;
+/// Interrupt line raised while console input is waiting to be read with `GETC`.
+pub const IRQ_KEYBOARD: u8 = 1;
+
 type Registers = [i16; MAX_REGISTERS];
 
+/// A function of the embedding application that guest code calls with `HOSTCALL`.
+/// It takes its arguments from and leaves its results in the registers and memory of the machine.
+pub type HostFn = Box<dyn FnMut(&mut Machine) -> Result<(), Error>>;
+
 pub struct State {
     pub r: Registers,
     pub pc: usize,
+    pub flags: Flags,
+    pub mode: Mode,
+    pub interrupts_enabled: bool,
 }
 
 pub struct Machine {
     pub pc: usize,
     pub r: Registers,
-    pub carry: bool,
-    pub cmp: ComparisonResult,
-    pub mem: [i16;std::u16::MAX as usize],
+    pub flags: Flags,
+    pub mode: Mode,
+    pub bus: Bus,
+    /// The data memory in the Harvard model.
+    pub ram: Option<Rc<RefCell<Ram>>>,
+    /// The code in the Harvard model, the whole memory in the von Neumann model.
     pub code: Vec<u8>,
-    pub screen: Vec<u8>,
+    pub memory_model: MemoryModel,
+    pub screen: Rc<RefCell<Screen>>,
+    pub timer: Rc<RefCell<Timer>>,
+    pub banked: Option<Rc<RefCell<BankedMemory>>>,
+    pub mmu: Option<Rc<RefCell<Mmu>>>,
+    /// Register snapshots saved by `CALLS` and interrupt entry.
     pub stack: Vec<State>,
+    /// Maximum number of snapshots on `stack`.
+    pub stack_depth: usize,
+    pub rets_restores: RetsRestores,
+    /// `POP` and `RET` with the stack pointer at or above this address underflow the stack.
+    pub stack_top: u16,
+    /// Lowest address the guest stack may grow to; pushing below it overflows the stack.
+    pub stack_limit: u16,
+    pub console: Box<dyn Console>,
+    pub interrupts_enabled: bool,
+    /// Interrupt requests not yet serviced, one bit per interrupt line.
+    pub pending_irqs: u16,
+    /// Set by `WAIT` until the next interrupt is serviced.
+    pub waiting: bool,
+    /// Set by `YIELD`; a `Scheduler` switches to the next task when it sees it.
+    pub yielded: bool,
+    pub error_policy: ErrorPolicy,
+    pub protection: Protection,
+    /// Data address at which `code` is readable and writable, one byte per cell, if any.
+    pub code_window: Option<u16>,
+    host_fns: HashMap<u16, HostFn>,
+}
+
+/// Reads the object file `filename`.
+pub fn read_object(filename: &String) -> Result<Vec<u8>, Error> {
+    let mut f = match File::open(filename) {
+        Ok(f) => f,
+        Err(e) => return Err(Error::FileNotFound(e.to_string())),
+    };
+    let metadata = match std::fs::metadata(filename) {
+        Ok(metadata) => metadata,
+        Err(e) => return Err(Error::CannotReadFileMetadata(e.to_string())),
+    };
+    let mut obj = vec![0; metadata.len() as usize];
+    match f.read_exact(&mut obj) {
+        Ok(_) => Ok(obj),
+        Err(_) => Err(Error::ObjectFileTooLarge(metadata.len() as usize)),
+    }
+}
+
+impl Default for Machine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Machine {
     
+    /// A machine with the default configuration of `MachineBuilder`.
     pub fn new() -> Self {
-        Machine {
-            pc: 0x0000,
-            r: [0x0000; 16],
-            carry: false,
-            cmp: ComparisonResult::None,
-            mem: [0x0000; std::u16::MAX as usize],
-            code: Vec::new(),
-            screen: vec![0x20; SCREEN_HEIGHT * SCREEN_WIDTH],
-            stack: Vec::new(),
-        }
+        MachineBuilder::new().build().expect("default configuration is valid")
+    }
+
+    /// Maps the data addresses `first..=last` to `device`, shadowing whatever was mapped there before.
+    pub fn attach(&mut self, first: u16, last: u16, device: Rc<RefCell<dyn Device>>) {
+        self.bus.attach(first, last, device);
+    }
+
+    /// Makes `f` callable from guest code as `HOSTCALL id`, replacing any function registered for `id`.
+    pub fn register_host_fn<F>(&mut self, id: u16, f: F)
+    where F: FnMut(&mut Machine) -> Result<(), Error> + 'static {
+        self.host_fns.insert(id, Box::new(f));
     }
 
     pub fn push(&mut self, pc: usize) -> Result<(), Error>{
-        if self.stack.len() >= STACK_SIZE {
+        if self.stack.len() >= self.stack_depth {
             return Err(Error::StackOverflow);
         }
         self.stack.push(State {
             r: self.r,
-            pc: pc
+            pc,
+            flags: self.flags,
+            mode: self.mode,
+            interrupts_enabled: self.interrupts_enabled,
         });
         Ok(())
     }
@@ -76,22 +173,340 @@ impl Machine {
         }
     }
 
-    pub fn load(&mut self, filename: &String) -> Result<(), Error> {
-        let mut f = match File::open(&filename) {
-            Ok(f) => f,
-            Err(e) => return Err(Error::FileNotFound(e.to_string())),
+    /// Pushes a word onto the guest stack.
+    fn push_word(&mut self, value: i16) -> Result<(), Error> {
+        let sp = match (self.r[SP] as u16).checked_sub(self.memory_model.word_size()) {
+            Some(sp) if sp >= self.stack_limit => sp,
+            _ => return Err(Error::StackOverflow),
+        };
+        self.write_mem(sp, value)?;
+        self.r[SP] = sp as i16;
+        Ok(())
+    }
+
+    /// Pops a word off the guest stack.
+    fn pop_word(&mut self) -> Result<i16, Error> {
+        let sp = self.r[SP] as u16;
+        if sp >= self.stack_top {
+            return Err(Error::StackUnderflow);
+        }
+        let value = self.read_mem(sp)?;
+        self.r[SP] = sp.wrapping_add(self.memory_model.word_size()) as i16;
+        Ok(value)
+    }
+
+    /// Address of the handler address for interrupt line `irq`.
+    pub fn interrupt_vector(&self, irq: u8) -> u16 {
+        IVT_BASE + irq as u16 * self.memory_model.word_size()
+    }
+
+    /// Address of the handler address for exception `cause`.
+    pub fn exception_vector(&self, cause: u16) -> u16 {
+        EVT_BASE + cause * self.memory_model.word_size()
+    }
+
+    /// Reads a vector table entry, which the machine does in supervisor mode.
+    fn read_vector(&mut self, vector: u16) -> Result<i16, Error> {
+        let mode = mem::replace(&mut self.mode, Mode::Supervisor);
+        let result = self.read_mem(vector);
+        self.mode = mode;
+        result
+    }
+
+    /// Number of interrupt lines, as many as the interrupt vector table holds.
+    pub fn interrupt_lines(&self) -> u8 {
+        ((EVT_BASE - IVT_BASE) / self.memory_model.word_size()) as u8
+    }
+
+    /// Requests an interrupt on line `irq`; it is serviced as soon as interrupts are enabled.
+    pub fn raise(&mut self, irq: u8) -> Result<(), Error> {
+        if irq >= self.interrupt_lines() {
+            return Err(Error::InvalidInterruptLine(irq));
+        }
+        self.pending_irqs |= 1 << irq;
+        Ok(())
+    }
+
+    /// Enters the handler of the lowest pending interrupt line if interrupts are enabled,
+    /// saving the registers, flags, pc and mode like `CALLS` does. Handlers run in supervisor mode.
+    /// Requests on lines without a handler are dropped.
+    fn service_interrupt(&mut self) -> Result<(), Error> {
+        if !self.interrupts_enabled {
+            return Ok(());
+        }
+        let mut pending = self.pending_irqs;
+        if self.console.input_ready() {
+            pending |= 1 << IRQ_KEYBOARD;
+        }
+        while pending != 0 {
+            let irq = pending.trailing_zeros() as u8;
+            pending &= !(1 << irq);
+            self.pending_irqs &= !(1 << irq);
+            let vector = self.read_vector(self.interrupt_vector(irq))?;
+            if vector == 0 {
+                continue;
+            }
+            let handler = self.jump_target(vector as u16)?;
+            self.push(self.pc)?;
+            self.mode = Mode::Supervisor;
+            self.interrupts_enabled = false;
+            self.waiting = false;
+            self.pc = handler;
+            break;
+        }
+        Ok(())
+    }
+
+    /// Advances the devices by one step and latches their interrupt requests.
+    fn tick(&mut self) {
+        self.bus.tick();
+        let lines = (1u32 << self.interrupt_lines()) - 1;
+        self.pending_irqs |= self.bus.interrupts() & lines as u16;
+    }
+
+    /// Effective address of a `[rN+imm]` operand, wrapping around the 16-bit address space.
+    fn indexed(&self, ra: u8, offset: i16) -> u16 {
+        (self.r[ra as usize] as u16).wrapping_add(offset as u16)
+    }
+
+    /// Fails with a protection fault unless the region containing `addr` allows `access`
+    /// and, in user mode, `addr` is a data address below `SUPERVISOR_BASE`.
+    fn check_access(&self, addr: u16, access: Access) -> Result<(), Error> {
+        let privileged = self.mode == Mode::User && access != Access::Execute && addr >= SUPERVISOR_BASE;
+        if privileged || !self.protection.allows(addr, access) {
+            return Err(Error::ProtectionFault { addr, pc: self.pc, kind: access });
+        }
+        Ok(())
+    }
+
+    /// Whether an MMU is present and translating addresses.
+    fn paging(&self) -> bool {
+        self.mmu.as_ref().is_some_and(|mmu| mmu.borrow().enabled())
+    }
+
+    /// Translates the virtual address `addr` to a physical address through the page table,
+    /// or fails with a page fault if the page is not present or does not allow `access`.
+    fn translate(&self, addr: u16, access: Access) -> Result<u16, Error> {
+        let mmu = match &self.mmu {
+            Some(mmu) if mmu.borrow().enabled() => mmu,
+            _ => return Ok(addr),
         };
-        let metadata = match std::fs::metadata(&filename) {
-            Ok(metadata) => metadata,
-            Err(e) => return Err(Error::CannotReadFileMetadata(e.to_string())),
+        let page = addr >> PAGE_SHIFT;
+        let cached = mmu.borrow_mut().lookup(page);
+        let entry = match cached {
+            Some(entry) => entry,
+            None => {
+                let table = mmu.borrow().page_table;
+                let entry = self.read_physical(table.wrapping_add(page * self.memory_model.word_size())).unwrap_or(0) as u16;
+                if entry & PTE_PRESENT != 0 {
+                    mmu.borrow_mut().insert(page, entry);
+                }
+                entry
+            },
         };
-        self.code = vec![0; metadata.len() as usize];
-        match f.read(&mut self.code) {
-            Ok(_) => Ok(()),
-            Err(_) => Err(Error::ObjectFileTooLarge(metadata.len() as usize)),
+        let allowed = entry & PTE_PRESENT != 0
+            && (access != Access::Write || entry & PTE_WRITABLE != 0)
+            && (self.mode == Mode::Supervisor || entry & PTE_USER != 0);
+        if !allowed {
+            let mut mmu = mmu.borrow_mut();
+            mmu.fault_addr = addr;
+            mmu.fault_status = match access {
+                Access::Read => FAULT_READ,
+                Access::Write => FAULT_WRITE,
+                Access::Execute => FAULT_EXECUTE,
+            };
+            return Err(Error::PageFault { addr, pc: self.pc, kind: access });
+        }
+        Ok((entry & 0xff00) | (addr & 0x00ff))
+    }
+
+    /// Index into `code` of the instruction at the pc.
+    fn fetch_index(&self) -> Result<usize, Error> {
+        if !self.paging() {
+            return Ok(self.pc);
+        }
+        Ok(self.translate(self.pc as u16, Access::Execute)? as usize)
+    }
+
+    /// Index into `code` if `addr` lies in the code window.
+    fn code_index(&self, addr: u16) -> Option<usize> {
+        if self.memory_model == MemoryModel::VonNeumann {
+            return None;
+        }
+        let base = self.code_window?;
+        let idx = addr.checked_sub(base)? as usize;
+        if idx < self.code.len() {
+            Some(idx)
+        }
+        else {
+            None
+        }
+    }
+
+    /// Reads a data cell as the guest sees it: subject to memory protection and address translation,
+    /// from the code window or else from whichever device is mapped at `addr`.
+    pub fn read_mem(&self, addr: u16) -> Result<i16, Error> {
+        self.check_access(addr, Access::Read)?;
+        self.read_physical(self.translate(addr, Access::Read)?)
+    }
+
+    fn read_physical(&self, addr: u16) -> Result<i16, Error> {
+        if let Some(idx) = self.code_index(addr) {
+            return Ok(self.code[idx] as i16);
+        }
+        match self.bus.read(addr) {
+            Some(value) => Ok(value),
+            None => match self.word_index(addr) {
+                Some((lo, hi)) => Ok(i16::from_le_bytes([self.code[lo], self.code[hi]])),
+                None => Err(Error::InvalidAddress(addr, self.pc)),
+            },
+        }
+    }
+
+    /// Writes a data cell as the guest sees it; writes to the code window store the low byte.
+    pub fn write_mem(&mut self, addr: u16, value: i16) -> Result<(), Error> {
+        self.check_access(addr, Access::Write)?;
+        let addr = self.translate(addr, Access::Write)?;
+        if let Some(idx) = self.code_index(addr) {
+            self.code[idx] = value as u8;
+            return Ok(());
+        }
+        if !self.bus.write(addr, value) {
+            let (lo, hi) = match self.word_index(addr) {
+                Some(idx) => idx,
+                None => return Err(Error::InvalidAddress(addr, self.pc)),
+            };
+            [self.code[lo], self.code[hi]] = value.to_le_bytes();
+        }
+        Ok(())
+    }
+
+    /// Indexes of the low and high byte of the word at `addr` in the von Neumann memory;
+    /// a word does not wrap around the end of the address space.
+    fn word_index(&self, addr: u16) -> Option<(usize, usize)> {
+        let lo = addr as usize;
+        let hi = addr.checked_add(1)? as usize;
+        if self.memory_model == MemoryModel::VonNeumann && lo < self.code.len() && hi < self.code.len() {
+            Some((lo, hi))
+        }
+        else {
+            None
+        }
+    }
+
+    /// Reads the character at `addr` for `PUTS`: a byte in the von Neumann memory, a cell otherwise.
+    fn read_char(&self, addr: u16) -> Result<i16, Error> {
+        self.check_access(addr, Access::Read)?;
+        let addr = self.translate(addr, Access::Read)?;
+        if self.bus.read(addr).is_none() {
+            if let Some((lo, _)) = self.word_index(addr) {
+                return Ok(self.code[lo] as i16);
+            }
+        }
+        self.read_physical(addr)
+    }
+
+    /// Reads a data word without protection checks, or `None` if there is no memory at `addr`.
+    fn peek(&self, addr: u16) -> Option<i16> {
+        if let Some(idx) = self.code_index(addr) {
+            return Some(self.code[idx] as i16);
+        }
+        self.bus.read(addr).or_else(|| self.word_index(addr).map(|(lo, hi)| i16::from_le_bytes([self.code[lo], self.code[hi]])))
+    }
+
+    /// Hex dump of the data cells `first..=last`, eight cells per line.
+    /// Lines starting inside the bank window are prefixed with the selected bank, e.g. `02:8000:`;
+    /// cells without memory are shown as `----`.
+    pub fn dump_memory(&self, first: u16, last: u16) -> String {
+        let mut out = String::new();
+        let mut addr = first as usize;
+        while addr <= last as usize {
+            let bank = self.banked.as_ref()
+                .map(|b| b.borrow())
+                .filter(|b| b.in_window(addr as u16))
+                .map(|b| b.bank);
+            match bank {
+                Some(bank) => out.push_str(&format!("{:02x}:{:04x}:", bank, addr)),
+                None => out.push_str(&format!("{:04x}:", addr)),
+            }
+            for a in addr..=(addr + 7).min(last as usize) {
+                match self.peek(a as u16) {
+                    Some(value) => out.push_str(&format!(" {:04x}", value as u16)),
+                    None => out.push_str(" ----"),
+                }
+            }
+            out.push('\n');
+            addr += 8;
+        }
+        out
+    }
+
+    /// Hex dump of a whole bank as seen through the bank window, whether it is selected or not.
+    pub fn dump_bank(&self, bank: usize) -> Option<String> {
+        let banked = self.banked.as_ref()?.borrow();
+        if bank >= banked.banks() {
+            return None;
+        }
+        let mut out = String::new();
+        for (i, line) in banked.page(bank).chunks(8).enumerate() {
+            out.push_str(&format!("{:02x}:{:04x}:", bank, banked.window as usize + i * 8));
+            for value in line {
+                out.push_str(&format!(" {:04x}", *value as u16));
+            }
+            out.push('\n');
+        }
+        Some(out)
+    }
+
+    /// The characters on the screen, one string per row, without trailing blanks.
+    pub fn screen_text(&self) -> Vec<String> {
+        self.screen.borrow().to_text().lines().map(|line| line.trim_end().to_string()).collect()
+    }
+
+    /// Checks that a computed jump target lies inside `code`; virtual targets are checked when fetched.
+    fn jump_target(&self, addr: u16) -> Result<usize, Error> {
+        let addr = addr as usize;
+        if !self.paging() && addr >= self.code.len() {
+            return Err(Error::InvalidJumpTarget(addr, self.pc));
+        }
+        Ok(addr)
+    }
+
+    /// The code byte at `idx`, which belongs to the instruction at the pc.
+    fn code_byte(&self, idx: usize) -> Result<u8, Error> {
+        match self.code.get(idx) {
+            Some(&byte) => Ok(byte),
+            None => Err(Error::InvalidJumpTarget(idx, self.pc)),
         }
     }
 
+    /// The two code bytes of the little-endian operand at `idx`.
+    fn code_word(&self, idx: usize) -> Result<[u8; 2], Error> {
+        Ok([self.code_byte(idx)?, self.code_byte(idx + 1)?])
+    }
+
+    fn compare(&mut self, a: i16, b: i16) {
+        (_, self.flags) = Flags::sub(a, b);
+    }
+
+    pub fn load(&mut self, filename: &String) -> Result<(), Error> {
+        self.load_code(&read_object(filename)?)
+    }
+
+    /// Loads an object: as the code in the Harvard model, from address 0 of the memory in the von Neumann model.
+    pub fn load_code(&mut self, obj: &[u8]) -> Result<(), Error> {
+        match self.memory_model {
+            MemoryModel::Harvard => self.code = obj.to_vec(),
+            MemoryModel::VonNeumann => {
+                if obj.len() > self.code.len() {
+                    return Err(Error::ObjectFileTooLarge(obj.len()));
+                }
+                self.code[..obj.len()].copy_from_slice(obj);
+            },
+        }
+        Ok(())
+    }
+
     pub fn run(&mut self) -> Result<(), Error> {
         loop {
             match self.step() {
@@ -103,355 +518,1067 @@ impl Machine {
         Ok(())
     }
 
+    /// Executes one instruction. Errors are returned to the host or, with `ErrorPolicy::Trap`,
+    /// delivered to the guest's exception handler.
     pub fn step(&mut self) -> Result<bool, Error> {
-        let opcode: Opcode = unsafe { std::mem::transmute(self.code[self.pc]) };
-        #[allow(unreachable_patterns)]
+        let pc = self.pc;
+        match self.execute() {
+            Err(e) if self.error_policy == ErrorPolicy::Trap => self.trap(e, pc),
+            result => result,
+        }
+    }
+
+    /// Continues at the exception handler for `e`, in supervisor mode, with the cause in r0 and the faulting pc in r1.
+    /// The state at the faulting instruction is saved like for an interrupt, so `IRET` retries it.
+    /// Errors without a cause, without a handler installed, or with a full call stack stay fatal.
+    fn trap(&mut self, e: Error, pc: usize) -> Result<bool, Error> {
+        let cause = match exception::cause(&e) {
+            Some(cause) => cause,
+            None => return Err(e),
+        };
+        let handler = match self.read_vector(self.exception_vector(cause)) {
+            Ok(0) | Err(_) => return Err(e),
+            Ok(vector) => match self.jump_target(vector as u16) {
+                Ok(handler) => handler,
+                Err(_) => return Err(e),
+            },
+        };
+        if self.push(pc).is_err() {
+            return Err(e);
+        }
+        if let Some(mmu) = &self.mmu {
+            if !matches!(e, Error::PageFault { .. }) {
+                mmu.borrow_mut().fault_status = FAULT_NONE;
+            }
+        }
+        self.r[0] = cause as i16;
+        self.r[1] = pc as i16;
+        self.pc = handler;
+        self.mode = Mode::Supervisor;
+        self.interrupts_enabled = false;
+        self.waiting = false;
+        Ok(true)
+    }
+
+    fn execute(&mut self) -> Result<bool, Error> {
+        self.service_interrupt()?;
+        if self.waiting {
+            self.tick();
+            return Ok(true);
+        }
+        self.check_access(self.pc as u16, Access::Execute)?;
+        let ppc = self.fetch_index()?;
+        let opcode = match Opcode::try_from(self.code_byte(ppc)?) {
+            Ok(opcode) => opcode,
+            Err(byte) => return Err(Error::UnknownOpcode(byte, self.pc)),
+        };
+        if self.mode == Mode::User && privilege::privileged(&opcode) {
+            return Err(Error::PrivilegedInstruction(self.code_byte(ppc)?, self.pc));
+        }
         match opcode {
             Opcode::CpRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
                 self.r[rd as usize] = self.r[rs as usize];
                 self.pc += 2;
             },
             Opcode::CpRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
                 self.r[rd as usize] = v;
                 self.pc += 4;
             },
             Opcode::CpMemR => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                let rs = self.code[self.pc+3] & 0x0f;
-                self.mem[addr as usize] = self.r[rs as usize];
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                let rs = self.code_byte(ppc+3)? & 0x0f;
+                self.write_mem(addr, self.r[rs as usize])?;
                 self.pc += 4;
             },
             Opcode::CpRMem => {
-                let rs = self.code[self.pc+1] & 0x0f;
-                let addr = u16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                self.r[rs as usize] = self.mem[addr as usize];
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                self.r[rs as usize] = self.read_mem(addr)?;
+                self.pc += 4;
+            },
+            Opcode::XchgRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                self.r.swap(rd as usize, rs as usize);
+                self.pc += 2;
+            },
+            Opcode::XchgRMem => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                let v = self.read_mem(addr)?;
+                self.write_mem(addr, self.r[rd as usize])?;
+                self.r[rd as usize] = v;
                 self.pc += 4;
             },
             Opcode::AddRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_add(self.r[rs as usize]);
+                (self.r[rd as usize], self.flags) = Flags::add(self.r[rd as usize], self.r[rs as usize]);
                 self.pc += 2;
             },
             Opcode::AddRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_add(v);
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::add(self.r[rd as usize], v);
                 self.pc += 4;
             },
             Opcode::AddRMem => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let addr = u16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_add(self.mem[addr as usize]);
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::add(self.r[rd as usize], self.read_mem(addr)?);
                 self.pc += 4;
             },
             Opcode::SubRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_sub(self.r[rs as usize]);
+                (self.r[rd as usize], self.flags) = Flags::sub(self.r[rd as usize], self.r[rs as usize]);
                 self.pc += 2;
             },
             Opcode::SubRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_sub(v);
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::sub(self.r[rd as usize], v);
                 self.pc += 4;
             },
             Opcode::SubRMem => {
-                let rs = self.code[self.pc+1] & 0x0f;
-                let addr = u16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                (self.r[rs as usize], self.carry) = self.r[rs as usize].overflowing_sub(self.mem[addr as usize]);
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rs as usize], self.flags) = Flags::sub(self.r[rs as usize], self.read_mem(addr)?);
                 self.pc += 4;
             },
             Opcode::MulRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_mul(self.r[rs as usize]);
+                (self.r[rd as usize], self.flags) = Flags::mul(self.r[rd as usize], self.r[rs as usize]);
                 self.pc += 2;
             },
             Opcode::MulRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_mul(v);
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::mul(self.r[rd as usize], v);
                 self.pc += 4;
             },
             Opcode::MulRMem => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let addr = u16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_mul(self.mem[addr as usize]);
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::mul(self.r[rd as usize], self.read_mem(addr)?);
                 self.pc += 4;
             },
             Opcode::DivRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
                 let v = self.r[rs as usize];
                 if v == 0 {
                     return Err(Error::DivisionByZero);
                 }
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_div(v);
+                (self.r[rd as usize], self.flags) = Flags::div(self.r[rd as usize], v);
                 self.pc += 2;
             },
             Opcode::DivRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
                 if v == 0 {
                     return Err(Error::DivisionByZero);
                 }
-                (self.r[rd as usize], self.carry) = self.r[rd as usize].overflowing_div(v);
+                (self.r[rd as usize], self.flags) = Flags::div(self.r[rd as usize], v);
                 self.pc += 4;
             },
             Opcode::DivRMem => {
-                let rs = self.code[self.pc+1] & 0x0f;
-                let addr = u16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                let v = self.mem[addr as usize];
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                let v = self.read_mem(addr)?;
                 if v == 0 {
                     return Err(Error::DivisionByZero);
                 }
-                (self.r[rs as usize], self.carry) = self.r[rs as usize].overflowing_div(v);
+                (self.r[rs as usize], self.flags) = Flags::div(self.r[rs as usize], v);
+                self.pc += 4;
+            },
+            Opcode::AdcRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                (self.r[rd as usize], self.flags) = Flags::adc(self.r[rd as usize], self.r[rs as usize], self.flags.c);
+                self.pc += 2;
+            },
+            Opcode::AdcRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::adc(self.r[rd as usize], v, self.flags.c);
+                self.pc += 4;
+            },
+            Opcode::AdcRMem => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::adc(self.r[rd as usize], self.read_mem(addr)?, self.flags.c);
+                self.pc += 4;
+            },
+            Opcode::SbcRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                (self.r[rd as usize], self.flags) = Flags::sbc(self.r[rd as usize], self.r[rs as usize], self.flags.c);
+                self.pc += 2;
+            },
+            Opcode::SbcRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::sbc(self.r[rd as usize], v, self.flags.c);
+                self.pc += 4;
+            },
+            Opcode::SbcRMem => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::sbc(self.r[rd as usize], self.read_mem(addr)?, self.flags.c);
+                self.pc += 4;
+            },
+            Opcode::MulWRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                let rh = (rd + 1) & 0x0f;
+                let (lo, hi, flags) = Flags::mulw(self.r[rd as usize], self.r[rs as usize]);
+                self.r[rd as usize] = lo;
+                self.r[rh as usize] = hi;
+                self.flags = flags;
+                self.pc += 2;
+            },
+            Opcode::DivWRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                let rh = (rd + 1) & 0x0f;
+                let v = self.r[rs as usize];
+                if v == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                match Flags::divw(self.r[rh as usize], self.r[rd as usize], v) {
+                    Some((quotient, remainder, flags)) => {
+                        self.r[rd as usize] = quotient;
+                        self.r[rh as usize] = remainder;
+                        self.flags = flags;
+                    },
+                    None => self.flags.v = true,
+                }
+                self.pc += 2;
+            },
+            Opcode::ModRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                let v = self.r[rs as usize];
+                if v == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                (self.r[rd as usize], self.flags) = Flags::rem(self.r[rd as usize], v);
+                self.pc += 2;
+            },
+            Opcode::ModRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                if v == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                (self.r[rd as usize], self.flags) = Flags::rem(self.r[rd as usize], v);
+                self.pc += 4;
+            },
+            Opcode::ModRMem => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                let v = self.read_mem(addr)?;
+                if v == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                (self.r[rd as usize], self.flags) = Flags::rem(self.r[rd as usize], v);
                 self.pc += 4;
             },
             Opcode::NegR => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                self.r[rd as usize] = -self.r[rd as usize];
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                (self.r[rd as usize], self.flags) = Flags::sub(0, self.r[rd as usize]);
                 self.pc += 2;
             },
             Opcode::NegMem => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                self.mem[addr as usize] = -self.mem[addr as usize];
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                let v;
+                (v, self.flags) = Flags::sub(0, self.read_mem(addr)?);
+                self.write_mem(addr, v)?;
                 self.pc += 3;
             },
             Opcode::XorRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
                 self.r[rd as usize] ^= self.r[rs as usize];
+                self.flags = Flags::logic(self.r[rd as usize]);
                 self.pc += 2;
             },
             Opcode::XorRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
                 self.r[rd as usize] ^= v;
+                self.flags = Flags::logic(self.r[rd as usize]);
                 self.pc += 4;
             },
             Opcode::XorRMem => {
-                let rs = self.code[self.pc+1] & 0x0f;
-                let addr = u16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                self.r[rs as usize] ^= self.mem[addr as usize];
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                self.r[rs as usize] ^= self.read_mem(addr)?;
+                self.flags = Flags::logic(self.r[rs as usize]);
                 self.pc += 4;
             },
             Opcode::AndRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
                 self.r[rd as usize] &= self.r[rs as usize];
+                self.flags = Flags::logic(self.r[rd as usize]);
                 self.pc += 2;
             },
             Opcode::AndRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
                 self.r[rd as usize] &= v;
+                self.flags = Flags::logic(self.r[rd as usize]);
                 self.pc += 4;
             },
             Opcode::AndRMem => {
-                let rs = self.code[self.pc+1] & 0x0f;
-                let addr = u16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                self.r[rs as usize] &= self.mem[addr as usize];
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                self.r[rs as usize] &= self.read_mem(addr)?;
+                self.flags = Flags::logic(self.r[rs as usize]);
                 self.pc += 4;
             },
             Opcode::OrRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
                 self.r[rd as usize] |= self.r[rs as usize];
+                self.flags = Flags::logic(self.r[rd as usize]);
                 self.pc += 2;
             },
             Opcode::OrRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
                 self.r[rd as usize] |= v;
+                self.flags = Flags::logic(self.r[rd as usize]);
                 self.pc += 4;
             },
             Opcode::OrRMem => {
-                let rs = self.code[self.pc+1] & 0x0f;
-                let addr = u16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                self.r[rs as usize] |= self.mem[addr as usize];
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                self.r[rs as usize] |= self.read_mem(addr)?;
+                self.flags = Flags::logic(self.r[rs as usize]);
                 self.pc += 4;
             },
             Opcode::NotR => {
-                let rd = self.code[self.pc+1] & 0x0f;
+                let rd = self.code_byte(ppc+1)? & 0x0f;
                 self.r[rd as usize] = !self.r[rd as usize];
+                self.flags = Flags::logic(self.r[rd as usize]);
                 self.pc += 2;
             },
             Opcode::NotMem => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                self.mem[addr as usize] = !self.mem[addr as usize];
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                let v = !self.read_mem(addr)?;
+                self.write_mem(addr, v)?;
+                self.flags = Flags::logic(v);
                 self.pc += 3;
             },
             Opcode::ShrRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
-                self.r[rd as usize] >>= self.r[rs as usize];
+                (self.r[rd as usize], self.flags) = Flags::shr(self.r[rd as usize], self.r[rs as usize]);
                 self.pc += 2;
             },
             Opcode::ShrRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                self.r[rd as usize] >>= v;
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::shr(self.r[rd as usize], v);
                 self.pc += 4;
             },
             Opcode::ShlRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
-                self.r[rd as usize] <<= self.r[rs as usize];
+                (self.r[rd as usize], self.flags) = Flags::shl(self.r[rd as usize], self.r[rs as usize]);
                 self.pc += 2;
             },
             Opcode::ShlRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
-                let v = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                self.r[rd as usize] <<= v;
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::shl(self.r[rd as usize], v);
                 self.pc += 4;
             },
+            Opcode::SarRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                (self.r[rd as usize], self.flags) = Flags::sar(self.r[rd as usize], self.r[rs as usize]);
+                self.pc += 2;
+            },
+            Opcode::SarRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::sar(self.r[rd as usize], v);
+                self.pc += 4;
+            },
+            Opcode::RolRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                (self.r[rd as usize], self.flags) = Flags::rol(self.r[rd as usize], self.r[rs as usize]);
+                self.pc += 2;
+            },
+            Opcode::RolRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::rol(self.r[rd as usize], v);
+                self.pc += 4;
+            },
+            Opcode::RorRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                (self.r[rd as usize], self.flags) = Flags::ror(self.r[rd as usize], self.r[rs as usize]);
+                self.pc += 2;
+            },
+            Opcode::RorRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::ror(self.r[rd as usize], v);
+                self.pc += 4;
+            },
+            Opcode::RclRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                (self.r[rd as usize], self.flags) = Flags::rcl(self.r[rd as usize], self.r[rs as usize], self.flags.c);
+                self.pc += 2;
+            },
+            Opcode::RclRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::rcl(self.r[rd as usize], v, self.flags.c);
+                self.pc += 4;
+            },
+            Opcode::RcrRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                (self.r[rd as usize], self.flags) = Flags::rcr(self.r[rd as usize], self.r[rs as usize], self.flags.c);
+                self.pc += 2;
+            },
+            Opcode::RcrRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                (self.r[rd as usize], self.flags) = Flags::rcr(self.r[rd as usize], v, self.flags.c);
+                self.pc += 4;
+            },
+            Opcode::BtRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                let mask = 1i16 << (self.r[rs as usize] as u16 % 16);
+                self.flags.c = self.r[rd as usize] & mask != 0;
+                self.pc += 2;
+            },
+            Opcode::BtRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let mask = 1i16 << (v as u16 % 16);
+                self.flags.c = self.r[rd as usize] & mask != 0;
+                self.pc += 4;
+            },
+            Opcode::BtsRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                let mask = 1i16 << (self.r[rs as usize] as u16 % 16);
+                self.flags.c = self.r[rd as usize] & mask != 0;
+                self.r[rd as usize] |= mask;
+                self.pc += 2;
+            },
+            Opcode::BtsRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let mask = 1i16 << (v as u16 % 16);
+                self.flags.c = self.r[rd as usize] & mask != 0;
+                self.r[rd as usize] |= mask;
+                self.pc += 4;
+            },
+            Opcode::BtrRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                let mask = 1i16 << (self.r[rs as usize] as u16 % 16);
+                self.flags.c = self.r[rd as usize] & mask != 0;
+                self.r[rd as usize] &= !mask;
+                self.pc += 2;
+            },
+            Opcode::BtrRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let mask = 1i16 << (v as u16 % 16);
+                self.flags.c = self.r[rd as usize] & mask != 0;
+                self.r[rd as usize] &= !mask;
+                self.pc += 4;
+            },
+            Opcode::BtcRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                let mask = 1i16 << (self.r[rs as usize] as u16 % 16);
+                self.flags.c = self.r[rd as usize] & mask != 0;
+                self.r[rd as usize] ^= mask;
+                self.pc += 2;
+            },
+            Opcode::BtcRImm => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                let v = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let mask = 1i16 << (v as u16 % 16);
+                self.flags.c = self.r[rd as usize] & mask != 0;
+                self.r[rd as usize] ^= mask;
+                self.pc += 4;
+            },
+            Opcode::PopCntRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                self.r[rd as usize] = self.r[rs as usize].count_ones() as i16;
+                self.flags = Flags::logic(self.r[rd as usize]);
+                self.pc += 2;
+            },
+            Opcode::ClzRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                self.r[rd as usize] = self.r[rs as usize].leading_zeros() as i16;
+                self.flags = Flags::logic(self.r[rd as usize]);
+                self.pc += 2;
+            },
+            Opcode::BSwapRR => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let rs = op & 0x0f;
+                self.r[rd as usize] = self.r[rs as usize].swap_bytes();
+                self.flags = Flags::logic(self.r[rd as usize]);
+                self.pc += 2;
+            },
             Opcode::CmpRR => {
-                let op = self.code[self.pc+1];
+                let op = self.code_byte(ppc+1)?;
                 let rd = op >> 4;
                 let rs = op & 0x0f;
                 let a = self.r[rd as usize];
                 let b = self.r[rs as usize];
-                if a < b {
-                    self.cmp = ComparisonResult::LessThan;
-                }
-                else if a > b {
-                    self.cmp = ComparisonResult::GreaterThan;
-                }
-                else {
-                    self.cmp = ComparisonResult::Equal;
-                }
+                self.compare(a, b);
                 self.pc += 2;
             },
             Opcode::CmpRImm => {
-                let rd = self.code[self.pc+1] & 0x0f;
+                let rd = self.code_byte(ppc+1)? & 0x0f;
                 let a = self.r[rd as usize];
-                let b = i16::from_le_bytes(self.code[self.pc+2..self.pc+4].try_into().expect("slice has incorrect length"));
-                if a < b {
-                    self.cmp = ComparisonResult::LessThan;
-                }
-                else if a > b {
-                    self.cmp = ComparisonResult::GreaterThan;
-                }
-                else {
-                    self.cmp = ComparisonResult::Equal;
-                }
+                let b = i16::from_le_bytes(self.code_word(ppc+2)?);
+                self.compare(a, b);
                 self.pc += 4;
-                
             },
             Opcode::CmpRMem => {
-                let rd = self.code[self.pc+1] & 0x0f;
+                let rd = self.code_byte(ppc+1)? & 0x0f;
                 let a = self.r[rd as usize];
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                let b = self.mem[addr as usize];
-                if a < b {
-                    self.cmp = ComparisonResult::LessThan;
-                }
-                else if a > b {
-                    self.cmp = ComparisonResult::GreaterThan;
-                }
-                else {
-                    self.cmp = ComparisonResult::Equal;
-                }
+                let addr = u16::from_le_bytes(self.code_word(ppc+2)?);
+                let b = self.read_mem(addr)?;
+                self.compare(a, b);
                 self.pc += 4;
             },
             Opcode::Be => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                match self.cmp {
-                    ComparisonResult::Equal => self.pc = addr as usize,
-                    _ => self.pc += 3,
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if self.flags.z {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
                 }
             },
             Opcode::Bne => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                match self.cmp {
-                    ComparisonResult::Equal => self.pc += 3,
-                    _ => self.pc = addr as usize,
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if !self.flags.z {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
                 }
             },
             Opcode::Bg => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                match self.cmp {
-                    ComparisonResult::GreaterThan => self.pc = addr as usize,
-                    _ => self.pc += 3,
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if self.flags.greater() {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
                 }
             },
             Opcode::Bge => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                match self.cmp {
-                    ComparisonResult::LessThan => self.pc += 3,
-                    _ => self.pc = addr as usize,
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if !self.flags.less() {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
                 }
             },
             Opcode::Bl => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                match self.cmp {
-                    ComparisonResult::LessThan => self.pc = addr as usize,
-                    _ => self.pc += 3,
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if self.flags.less() {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
                 }
             },
             Opcode::Ble => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                match self.cmp {
-                    ComparisonResult::GreaterThan => self.pc += 3,
-                    _ => self.pc = addr as usize,
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if !self.flags.greater() {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
                 }
             },
             Opcode::Bc => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                match self.carry {
-                    false => self.pc += 3,
-                    true => self.pc = addr as usize,
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if self.flags.c {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
+                }
+            },
+            Opcode::Bnc => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if !self.flags.c {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
+                }
+            },
+            Opcode::Bn => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if self.flags.n {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
+                }
+            },
+            Opcode::Bnn => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if !self.flags.n {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
+                }
+            },
+            Opcode::Bv => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if self.flags.v {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
+                }
+            },
+            Opcode::Bnv => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if !self.flags.v {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
+                }
+            },
+            Opcode::Ba => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if self.flags.above() {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
+                }
+            },
+            Opcode::Bbe => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                if !self.flags.above() {
+                    self.pc = self.jump_target(addr)?;
+                }
+                else {
+                    self.pc += 3;
                 }
             },
             Opcode::Jmp => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
-                self.pc = addr as usize;
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                self.pc = self.jump_target(addr)?;
+            },
+            Opcode::JmpR => {
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                self.pc = self.jump_target(self.r[rs as usize] as u16)?;
+            },
+            Opcode::JmpInd => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                self.pc = self.jump_target(self.read_mem(addr)? as u16)?;
             },
             Opcode::PutS => {
-                
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                let mut s = Vec::new();
+                let mut addr = addr;
+                loop {
+                    let c = self.read_char(addr)?;
+                    if c == 0 {
+                        break;
+                    }
+                    s.push(c as u8);
+                    if addr == u16::MAX {
+                        break;
+                    }
+                    addr += 1;
+                }
+                self.console.puts(&s);
+                self.pc += 3;
             },
             Opcode::GetC => {
-                
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                self.r[rd as usize] = match self.console.getc() {
+                    Some(c) => c as i16,
+                    None => -1,
+                };
+                self.pc += 2;
+            },
+            Opcode::PutC => {
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                self.console.putc(self.r[rs as usize] as u8);
+                self.pc += 2;
+            },
+            Opcode::PutN => {
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                self.console.puts(self.r[rs as usize].to_string().as_bytes());
+                self.pc += 2;
             },
             Opcode::Call => {
-                let addr = u16::from_le_bytes(self.code[self.pc+1..self.pc+3].try_into().expect("slice has incorrect length"));
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                self.push_word((self.pc+3) as i16)?;
+                self.pc = self.jump_target(addr)?;
+            }
+            Opcode::CallR => {
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                let addr = self.jump_target(self.r[rs as usize] as u16)?;
+                self.push_word((self.pc+2) as i16)?;
+                self.pc = addr;
+            },
+            Opcode::Ret => {
+                let addr = self.pop_word()?;
+                self.pc = self.jump_target(addr as u16)?;
+            }
+            Opcode::CallS => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
                 match self.push(self.pc+3) {
                     Ok(()) => (),
                     Err(e) => return Err(e),
                 }
-                self.pc = addr as usize;
+                self.pc = self.jump_target(addr)?;
             }
-            Opcode::Ret => {
+            Opcode::RetS => {
                 match self.pop() {
                     Ok(state) => {
-                        self.r = state.r;
+                        match self.rets_restores {
+                            RetsRestores::All => self.r = state.r,
+                            RetsRestores::CalleeSaved => self.r[FIRST_CALLEE_SAVED..].copy_from_slice(&state.r[FIRST_CALLEE_SAVED..]),
+                        }
                         self.pc = state.pc;
                     }
                     Err(e) => return Err(e),
                 }
             }
+            Opcode::Push => {
+                let rs = self.code_byte(ppc+1)? & 0x0f;
+                self.push_word(self.r[rs as usize])?;
+                self.pc += 2;
+            },
+            Opcode::Pop => {
+                let rd = self.code_byte(ppc+1)? & 0x0f;
+                self.r[rd as usize] = self.pop_word()?;
+                self.pc += 2;
+            },
+            Opcode::Nop => {
+                self.pc += 1;
+            },
+            Opcode::Ei => {
+                self.interrupts_enabled = true;
+                self.pc += 1;
+            },
+            Opcode::Di => {
+                self.interrupts_enabled = false;
+                self.pc += 1;
+            },
+            Opcode::IRet => {
+                let state = self.pop()?;
+                self.r = state.r;
+                self.pc = state.pc;
+                self.flags = state.flags;
+                self.mode = state.mode;
+                self.interrupts_enabled = state.interrupts_enabled;
+            },
+            Opcode::SysCall => {
+                let n = u16::from_le_bytes(self.code_word(ppc+1)?);
+                let handler = match self.read_vector(self.exception_vector(EXC_SYSCALL))? {
+                    0 => return Err(Error::UnhandledSyscall(n, self.pc)),
+                    vector => self.jump_target(vector as u16)?,
+                };
+                self.push(self.pc+3)?;
+                self.mode = Mode::Supervisor;
+                self.r[RETURN_VALUE] = n as i16;
+                self.pc = handler;
+            },
+            Opcode::SysRet => {
+                let state = self.pop()?;
+                self.r[FIRST_CALLEE_SAVED..].copy_from_slice(&state.r[FIRST_CALLEE_SAVED..]);
+                self.pc = state.pc;
+                self.mode = state.mode;
+            },
+            Opcode::HostCall => {
+                let id = u16::from_le_bytes(self.code_word(ppc+1)?);
+                // The function is taken out of the registry while it runs, as it borrows the machine.
+                let mut f = match self.host_fns.remove(&id) {
+                    Some(f) => f,
+                    None => return Err(Error::UnknownHostFunction(id, self.pc)),
+                };
+                let result = f(self);
+                self.host_fns.entry(id).or_insert(f);
+                result?;
+                self.pc += 3;
+            },
+            Opcode::Yield => {
+                self.yielded = true;
+                self.pc += 1;
+            },
+            Opcode::JmpU => {
+                let addr = u16::from_le_bytes(self.code_word(ppc+1)?);
+                self.pc = self.jump_target(addr)?;
+                self.mode = Mode::User;
+            },
+            Opcode::Wait => {
+                if !self.interrupts_enabled {
+                    return Err(Error::WaitWithInterruptsDisabled(self.pc));
+                }
+                self.waiting = true;
+                self.pc += 1;
+            },
             Opcode::Halt => {
                 return Ok(false);
             },
-            _ => return Err(Error::UnknownOpcode(self.code[self.pc], self.pc)),
+            Opcode::CpRInd => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                self.r[rd as usize] = self.read_mem(self.indexed(ra, 0))?;
+                self.pc += 2;
+            },
+            Opcode::CpIndR => {
+                let op = self.code_byte(ppc+1)?;
+                let ra = op >> 4;
+                let rs = op & 0x0f;
+                let addr = self.indexed(ra, 0);
+                self.write_mem(addr, self.r[rs as usize])?;
+                self.pc += 2;
+            },
+            Opcode::CpRIdx => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                self.r[rd as usize] = self.read_mem(self.indexed(ra, offset))?;
+                self.pc += 4;
+            },
+            Opcode::CpIdxR => {
+                let op = self.code_byte(ppc+1)?;
+                let ra = op >> 4;
+                let rs = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let addr = self.indexed(ra, offset);
+                self.write_mem(addr, self.r[rs as usize])?;
+                self.pc += 4;
+            },
+            Opcode::CpRIndInc => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let v = self.read_mem(self.indexed(ra, 0))?;
+                self.r[ra as usize] = self.r[ra as usize].wrapping_add(self.memory_model.word_size() as i16);
+                self.r[rd as usize] = v;
+                self.pc += 2;
+            },
+            Opcode::CpIndIncR => {
+                let op = self.code_byte(ppc+1)?;
+                let ra = op >> 4;
+                let rs = op & 0x0f;
+                let addr = self.indexed(ra, 0);
+                self.write_mem(addr, self.r[rs as usize])?;
+                self.r[ra as usize] = self.r[ra as usize].wrapping_add(self.memory_model.word_size() as i16);
+                self.pc += 2;
+            },
+            Opcode::AddRInd => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let v = self.read_mem(self.indexed(ra, 0))?;
+                (self.r[rd as usize], self.flags) = Flags::add(self.r[rd as usize], v);
+                self.pc += 2;
+            },
+            Opcode::AddRIdx => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let v = self.read_mem(self.indexed(ra, offset))?;
+                (self.r[rd as usize], self.flags) = Flags::add(self.r[rd as usize], v);
+                self.pc += 4;
+            },
+            Opcode::SubRInd => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let v = self.read_mem(self.indexed(ra, 0))?;
+                (self.r[rd as usize], self.flags) = Flags::sub(self.r[rd as usize], v);
+                self.pc += 2;
+            },
+            Opcode::SubRIdx => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let v = self.read_mem(self.indexed(ra, offset))?;
+                (self.r[rd as usize], self.flags) = Flags::sub(self.r[rd as usize], v);
+                self.pc += 4;
+            },
+            Opcode::MulRInd => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let v = self.read_mem(self.indexed(ra, 0))?;
+                (self.r[rd as usize], self.flags) = Flags::mul(self.r[rd as usize], v);
+                self.pc += 2;
+            },
+            Opcode::MulRIdx => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let v = self.read_mem(self.indexed(ra, offset))?;
+                (self.r[rd as usize], self.flags) = Flags::mul(self.r[rd as usize], v);
+                self.pc += 4;
+            },
+            Opcode::DivRInd => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let v = self.read_mem(self.indexed(ra, 0))?;
+                if v == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                (self.r[rd as usize], self.flags) = Flags::div(self.r[rd as usize], v);
+                self.pc += 2;
+            },
+            Opcode::DivRIdx => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let v = self.read_mem(self.indexed(ra, offset))?;
+                if v == 0 {
+                    return Err(Error::DivisionByZero);
+                }
+                (self.r[rd as usize], self.flags) = Flags::div(self.r[rd as usize], v);
+                self.pc += 4;
+            },
+            Opcode::XorRInd => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                self.r[rd as usize] ^= self.read_mem(self.indexed(ra, 0))?;
+                self.flags = Flags::logic(self.r[rd as usize]);
+                self.pc += 2;
+            },
+            Opcode::XorRIdx => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                self.r[rd as usize] ^= self.read_mem(self.indexed(ra, offset))?;
+                self.flags = Flags::logic(self.r[rd as usize]);
+                self.pc += 4;
+            },
+            Opcode::AndRInd => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                self.r[rd as usize] &= self.read_mem(self.indexed(ra, 0))?;
+                self.flags = Flags::logic(self.r[rd as usize]);
+                self.pc += 2;
+            },
+            Opcode::AndRIdx => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                self.r[rd as usize] &= self.read_mem(self.indexed(ra, offset))?;
+                self.flags = Flags::logic(self.r[rd as usize]);
+                self.pc += 4;
+            },
+            Opcode::OrRInd => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                self.r[rd as usize] |= self.read_mem(self.indexed(ra, 0))?;
+                self.flags = Flags::logic(self.r[rd as usize]);
+                self.pc += 2;
+            },
+            Opcode::OrRIdx => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                self.r[rd as usize] |= self.read_mem(self.indexed(ra, offset))?;
+                self.flags = Flags::logic(self.r[rd as usize]);
+                self.pc += 4;
+            },
+            Opcode::CmpRInd => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let a = self.r[rd as usize];
+                let b = self.read_mem(self.indexed(ra, 0))?;
+                self.compare(a, b);
+                self.pc += 2;
+            },
+            Opcode::CmpRIdx => {
+                let op = self.code_byte(ppc+1)?;
+                let rd = op >> 4;
+                let ra = op & 0x0f;
+                let offset = i16::from_le_bytes(self.code_word(ppc+2)?);
+                let a = self.r[rd as usize];
+                let b = self.read_mem(self.indexed(ra, offset))?;
+                self.compare(a, b);
+                self.pc += 4;
+            },
         }
+        self.tick();
         Ok(true)
     }
 }
@@ -0,0 +1,25 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+/// How code and data share the address space.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MemoryModel {
+    /// Code bytes and data cells live in separate address spaces.
+    #[default]
+    Harvard,
+    /// Code and data share one byte-addressed memory; a data word is a little-endian byte pair.
+    /// Devices keep one word-wide register per address.
+    VonNeumann,
+}
+
+impl MemoryModel {
+    /// Address distance between two consecutive data words.
+    pub fn word_size(&self) -> u16 {
+        match self {
+            MemoryModel::Harvard => 1,
+            MemoryModel::VonNeumann => 2,
+        }
+    }
+}
@@ -0,0 +1,102 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use bus::Device;
+
+/// Bit 0 enables address translation. Writing flushes the TLB.
+pub const MMU_CONTROL: u16 = 0xef30;
+/// Physical address of the page table. Writing flushes the TLB.
+pub const MMU_PAGE_TABLE: u16 = 0xef31;
+/// Virtual address of the last page fault (read only).
+pub const MMU_FAULT_ADDR: u16 = 0xef32;
+/// How the last exception delivered was a page fault (read only): `FAULT_NONE` if it was not,
+/// else the denied access.
+pub const MMU_FAULT_STATUS: u16 = 0xef33;
+
+pub const FAULT_NONE: u16 = 0;
+pub const FAULT_READ: u16 = 1;
+pub const FAULT_WRITE: u16 = 2;
+pub const FAULT_EXECUTE: u16 = 3;
+
+/// A page spans 256 addresses; the high byte of an address selects its page.
+pub const PAGE_SHIFT: u16 = 8;
+pub const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
+
+/// Page table entry bits; the high byte of an entry is the physical page.
+pub const PTE_PRESENT: u16 = 0x0001;
+pub const PTE_WRITABLE: u16 = 0x0002;
+pub const PTE_USER: u16 = 0x0004;
+
+pub const TLB_ENTRIES: usize = 8;
+
+/// Memory management unit translating virtual to physical addresses through a page table
+/// with one word per page, caching recently used entries in a TLB.
+#[derive(Default)]
+pub struct Mmu {
+    pub control: u16,
+    pub page_table: u16,
+    pub fault_addr: u16,
+    pub fault_status: u16,
+    /// Cached (page, entry) pairs, replaced round robin.
+    tlb: Vec<(u16, u16)>,
+    next: usize,
+    pub tlb_hits: u64,
+    pub tlb_misses: u64,
+}
+
+impl Mmu {
+    pub fn enabled(&self) -> bool {
+        self.control & 1 != 0
+    }
+
+    pub fn flush(&mut self) {
+        self.tlb.clear();
+        self.next = 0;
+    }
+
+    /// The cached entry for `page`, counting the lookup as a TLB hit or miss.
+    pub fn lookup(&mut self, page: u16) -> Option<u16> {
+        match self.tlb.iter().find(|(p, _)| *p == page) {
+            Some(&(_, entry)) => {
+                self.tlb_hits += 1;
+                Some(entry)
+            },
+            None => {
+                self.tlb_misses += 1;
+                None
+            },
+        }
+    }
+
+    pub fn insert(&mut self, page: u16, entry: u16) {
+        if self.tlb.len() < TLB_ENTRIES {
+            self.tlb.push((page, entry));
+        }
+        else {
+            self.tlb[self.next] = (page, entry);
+            self.next = (self.next + 1) % TLB_ENTRIES;
+        }
+    }
+}
+
+impl Device for Mmu {
+    fn read(&mut self, addr: u16) -> i16 {
+        match addr {
+            MMU_CONTROL => self.control as i16,
+            MMU_PAGE_TABLE => self.page_table as i16,
+            MMU_FAULT_ADDR => self.fault_addr as i16,
+            _ => self.fault_status as i16,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: i16) {
+        match addr {
+            MMU_CONTROL => self.control = value as u16,
+            MMU_PAGE_TABLE => self.page_table = value as u16,
+            _ => return,
+        }
+        self.flush();
+    }
+}
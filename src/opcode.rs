@@ -4,8 +4,9 @@
  */
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::fmt;
-use std::fmt::{Display, Debug};
+use std::fmt::Display;
 
 #[repr(u8)]
 pub enum Opcode {
@@ -58,11 +59,108 @@ pub enum Opcode {
     Call,
     Ret,
     Halt,
+    CpRInd,
+    CpIndR,
+    CpRIdx,
+    CpIdxR,
+    CpRIndInc,
+    CpIndIncR,
+    AddRInd,
+    AddRIdx,
+    SubRInd,
+    SubRIdx,
+    MulRInd,
+    MulRIdx,
+    DivRInd,
+    DivRIdx,
+    XorRInd,
+    XorRIdx,
+    AndRInd,
+    AndRIdx,
+    OrRInd,
+    OrRIdx,
+    CmpRInd,
+    CmpRIdx,
+    JmpR,
+    JmpInd,
+    CallR,
+    Bnc,
+    Bn,
+    Bnn,
+    Bv,
+    Bnv,
+    Ba,
+    Bbe,
+    AdcRR,
+    AdcRImm,
+    AdcRMem,
+    SbcRR,
+    SbcRImm,
+    SbcRMem,
+    MulWRR,
+    DivWRR,
+    ModRR,
+    ModRImm,
+    ModRMem,
+    SarRR,
+    SarRImm,
+    RolRR,
+    RolRImm,
+    RorRR,
+    RorRImm,
+    RclRR,
+    RclRImm,
+    RcrRR,
+    RcrRImm,
+    BtRR,
+    BtRImm,
+    BtsRR,
+    BtsRImm,
+    BtrRR,
+    BtrRImm,
+    BtcRR,
+    BtcRImm,
+    PopCntRR,
+    ClzRR,
+    BSwapRR,
+    XchgRR,
+    XchgRMem,
+    Nop,
+    PutC,
+    PutN,
+    Ei,
+    Di,
+    IRet,
+    Wait,
+    Push,
+    Pop,
+    CallS,
+    RetS,
+    SysCall,
+    SysRet,
+    JmpU,
+    HostCall,
+    Yield,
+}
+
+/// The last opcode; new opcodes are appended to `Opcode`, so this must follow.
+const LAST_OPCODE: Opcode = Opcode::Yield;
+
+impl TryFrom<u8> for Opcode {
+    type Error = u8;
+
+    fn try_from(byte: u8) -> Result<Self, u8> {
+        if byte < Opcode::CpRR as u8 || byte > LAST_OPCODE as u8 {
+            return Err(byte);
+        }
+        // The discriminants are contiguous from `CpRR` to `LAST_OPCODE`.
+        Ok(unsafe { std::mem::transmute::<u8, Opcode>(byte) })
+    }
 }
 
 pub type Literal = String;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug)]
 pub enum TokenType {
     Directive,
     Identifier,
@@ -70,17 +168,41 @@ pub enum TokenType {
     DecNumber,
     HexNumber,
     BinNumber,
+    LeftBracket,
+    RightBracket,
+    Plus,
+    Minus,
+    PostIncrement,
     Cp,
+    Xchg,
     Add,
     Sub,
     Mul,
     Div,
+    Neg,
+    Adc,
+    Sbc,
+    MulW,
+    DivW,
+    Mod,
     Xor,
     And,
     Or,
     Not,
     Shl,
     Shr,
+    Sar,
+    Rol,
+    Ror,
+    Rcl,
+    Rcr,
+    Bt,
+    Bts,
+    Btr,
+    Btc,
+    PopCnt,
+    Clz,
+    BSwap,
     Cmp,
     Be,
     Bne,
@@ -89,27 +211,44 @@ pub enum TokenType {
     Bl,
     Ble,
     Bc,
+    Bnc,
+    Bn,
+    Bnn,
+    Bv,
+    Bnv,
+    Ba,
+    Bbe,
     Jmp,
     Call,
     Ret,
+    CallS,
+    RetS,
+    Push,
+    Pop,
     Puts,
+    Putc,
+    Putn,
     Getc,
+    Nop,
+    Ei,
+    Di,
+    IRet,
+    Wait,
+    SysCall,
+    SysRet,
+    JmpU,
+    HostCall,
+    Yield,
     Halt,
     Eof,
 }
 
-impl Debug for TokenType {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:?}", self)
-    }
-}
-
-struct Token {
-    ttype: TokenType,
-    lexeme: String,
-    literal: Option<Literal>,
-    line: usize,
-    position: usize,
+pub struct Token {
+    pub ttype: TokenType,
+    pub lexeme: String,
+    pub literal: Option<Literal>,
+    pub line: usize,
+    pub position: usize,
 }
 
 impl Token {
@@ -132,7 +271,7 @@ impl Display for Token {
 
 
 
-struct Scanner {
+pub struct Scanner {
     source: String,
     tokens: Vec<Token>,
     start_idx: usize,
@@ -146,16 +285,36 @@ impl Scanner {
     pub fn new(source: String) -> Self {
         let mut keywords = HashMap::new();
         keywords.insert("cp".to_string(), TokenType::Cp);
+        keywords.insert("mv".to_string(), TokenType::Cp);
+        keywords.insert("xchg".to_string(), TokenType::Xchg);
         keywords.insert("add".to_string(), TokenType::Add);
         keywords.insert("sub".to_string(), TokenType::Sub);
         keywords.insert("mul".to_string(), TokenType::Mul);
         keywords.insert("div".to_string(), TokenType::Div);
+        keywords.insert("neg".to_string(), TokenType::Neg);
+        keywords.insert("adc".to_string(), TokenType::Adc);
+        keywords.insert("sbc".to_string(), TokenType::Sbc);
+        keywords.insert("mulw".to_string(), TokenType::MulW);
+        keywords.insert("divw".to_string(), TokenType::DivW);
+        keywords.insert("mod".to_string(), TokenType::Mod);
         keywords.insert("xor".to_string(), TokenType::Xor);
         keywords.insert("and".to_string(), TokenType::And);
         keywords.insert("or".to_string(), TokenType::Or);
         keywords.insert("not".to_string(), TokenType::Not);
         keywords.insert("shl".to_string(), TokenType::Shl);
         keywords.insert("shr".to_string(), TokenType::Shr);
+        keywords.insert("sar".to_string(), TokenType::Sar);
+        keywords.insert("rol".to_string(), TokenType::Rol);
+        keywords.insert("ror".to_string(), TokenType::Ror);
+        keywords.insert("rcl".to_string(), TokenType::Rcl);
+        keywords.insert("rcr".to_string(), TokenType::Rcr);
+        keywords.insert("bt".to_string(), TokenType::Bt);
+        keywords.insert("bts".to_string(), TokenType::Bts);
+        keywords.insert("btr".to_string(), TokenType::Btr);
+        keywords.insert("btc".to_string(), TokenType::Btc);
+        keywords.insert("popcnt".to_string(), TokenType::PopCnt);
+        keywords.insert("clz".to_string(), TokenType::Clz);
+        keywords.insert("bswap".to_string(), TokenType::BSwap);
         keywords.insert("cmp".to_string(), TokenType::Cmp);
         keywords.insert("be".to_string(), TokenType::Be);
         keywords.insert("bne".to_string(), TokenType::Bne);
@@ -164,11 +323,39 @@ impl Scanner {
         keywords.insert("bl".to_string(), TokenType::Bl);
         keywords.insert("ble".to_string(), TokenType::Ble);
         keywords.insert("bc".to_string(), TokenType::Bc);
-        keywords.insert("add".to_string(), TokenType::Jmp);
+        keywords.insert("bnc".to_string(), TokenType::Bnc);
+        keywords.insert("bn".to_string(), TokenType::Bn);
+        keywords.insert("bnn".to_string(), TokenType::Bnn);
+        keywords.insert("bv".to_string(), TokenType::Bv);
+        keywords.insert("bnv".to_string(), TokenType::Bnv);
+        keywords.insert("ba".to_string(), TokenType::Ba);
+        keywords.insert("bbe".to_string(), TokenType::Bbe);
+        // aliases
+        keywords.insert("bz".to_string(), TokenType::Be);
+        keywords.insert("bnz".to_string(), TokenType::Bne);
+        keywords.insert("bb".to_string(), TokenType::Bc);
+        keywords.insert("bae".to_string(), TokenType::Bnc);
+        keywords.insert("jmp".to_string(), TokenType::Jmp);
         keywords.insert("call".to_string(), TokenType::Call);
         keywords.insert("ret".to_string(), TokenType::Ret);
+        keywords.insert("calls".to_string(), TokenType::CallS);
+        keywords.insert("rets".to_string(), TokenType::RetS);
+        keywords.insert("push".to_string(), TokenType::Push);
+        keywords.insert("pop".to_string(), TokenType::Pop);
         keywords.insert("puts".to_string(), TokenType::Puts);
+        keywords.insert("putc".to_string(), TokenType::Putc);
+        keywords.insert("putn".to_string(), TokenType::Putn);
         keywords.insert("getc".to_string(), TokenType::Getc);
+        keywords.insert("nop".to_string(), TokenType::Nop);
+        keywords.insert("ei".to_string(), TokenType::Ei);
+        keywords.insert("di".to_string(), TokenType::Di);
+        keywords.insert("iret".to_string(), TokenType::IRet);
+        keywords.insert("wait".to_string(), TokenType::Wait);
+        keywords.insert("syscall".to_string(), TokenType::SysCall);
+        keywords.insert("sysret".to_string(), TokenType::SysRet);
+        keywords.insert("jmpu".to_string(), TokenType::JmpU);
+        keywords.insert("hostcall".to_string(), TokenType::HostCall);
+        keywords.insert("yield".to_string(), TokenType::Yield);
         keywords.insert("halt".to_string(), TokenType::Halt);
         Self {
             source,
@@ -201,10 +388,10 @@ impl Scanner {
     }
 
     fn peek(&mut self) -> char {
-        if self.current_idx >= self.source.len()-1 {
+        if self.at_end() {
             return '\0';
         }
-        self.source.chars().nth(self.current_idx+1).unwrap_or('\0')
+        self.source.chars().nth(self.current_idx).unwrap_or('\0')
     }
 
     fn matches(&mut self, expected: char) -> bool {
@@ -242,7 +429,9 @@ impl Scanner {
         }
         if self.at_end() {
             self.error("unterminated string".to_string(), self.line, 0);
+            return;
         }
+        self.advance();
         let source = &self.source.to_string();
         let token = &source[self.start_idx+1..self.current_idx-1];
         self.add_literal(TokenType::String, Some(token.to_string()));
@@ -255,11 +444,7 @@ impl Scanner {
     }
 
     fn consume_directive(&mut self) {
-        while !self.at_end() && match self.peek() {
-            'a'..='z' | 'A'..='Z' => true,
-            _ => false,
-        } 
-        {
+        while !self.at_end() && self.peek().is_ascii_alphabetic() {
             self.advance();
         }
         let source = &self.source.to_string();
@@ -268,11 +453,7 @@ impl Scanner {
     }
 
     fn consume_hex_number(&mut self) {
-        while !self.at_end() && match self.peek() {
-            '0'..='9' | 'a'..='f' | 'A'..='F' => true,
-            _ => false,
-        } 
-        {
+        while !self.at_end() && self.peek().is_ascii_hexdigit() {
             self.advance();
         }
         let source = &self.source.to_string();
@@ -281,11 +462,7 @@ impl Scanner {
     }
     
     fn consume_dec_number(&mut self) {
-        while !self.at_end() && match self.peek() {
-            '0'..='9' => true,
-            _ => false,
-        } 
-        {
+        while !self.at_end() && self.peek().is_ascii_digit() {
             self.advance();
         }
         let source = &self.source.to_string();
@@ -294,11 +471,7 @@ impl Scanner {
     }
     
     fn consume_bin_number(&mut self) {
-        while !self.at_end() && match self.peek() {
-            '0'..='1' => true,
-            _ => false,
-        } 
-        {
+        while !self.at_end() && matches!(self.peek(), '0'..='1') {
             self.advance();
         }
         let source = &self.source.to_string();
@@ -307,11 +480,7 @@ impl Scanner {
     }
     
     fn consume_identifier(&mut self) {
-        while !self.at_end() && match self.peek() {
-            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => true,
-            _ => false,
-        } 
-        {
+        while !self.at_end() && matches!(self.peek(), 'a'..='z' | 'A'..='Z' | '0'..='9' | '_') {
             self.advance();
         }
         let source = &self.source.to_string();
@@ -333,6 +502,14 @@ impl Scanner {
             '!' => self.consume_bin_number(),
             'a'..='z' | 'A'..='Z' => self.consume_identifier(),
             '"' => self.consume_string(),
+            '[' => self.add_token(TokenType::LeftBracket),
+            ']' => self.add_token(TokenType::RightBracket),
+            '+' => if self.matches(']') {
+                self.add_token(TokenType::PostIncrement)
+            } else {
+                self.add_token(TokenType::Plus)
+            },
+            '-' => self.add_token(TokenType::Minus),
             '\n' => self.line += 1,
             _ => log::error!("unexpected character {} at line {}", c, self.current_idx),
         }
@@ -0,0 +1,30 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use opcode::Opcode;
+
+/// Addresses from here up (the I/O page, the screen window and the vector tables)
+/// can only be accessed in supervisor mode.
+pub const SUPERVISOR_BASE: u16 = 0xef00;
+
+/// Privilege level the machine runs at.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// May execute every instruction and access every address.
+    #[default]
+    Supervisor,
+    /// May not execute privileged instructions or access addresses from `SUPERVISOR_BASE` up.
+    User,
+}
+
+/// Whether `opcode` touches the console, the interrupt state, the privilege level or the host,
+/// or stops the machine, and therefore faults in user mode. User programs ask the kernel
+/// through `SYSCALL` instead.
+pub fn privileged(opcode: &Opcode) -> bool {
+    matches!(opcode,
+        Opcode::PutS | Opcode::PutC | Opcode::PutN | Opcode::GetC
+        | Opcode::Ei | Opcode::Di | Opcode::IRet | Opcode::Wait
+        | Opcode::SysRet | Opcode::JmpU | Opcode::HostCall | Opcode::Halt)
+}
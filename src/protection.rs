@@ -0,0 +1,73 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    Execute,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => write!(f, "read"),
+            Access::Write => write!(f, "write"),
+            Access::Execute => write!(f, "execute"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Permissions {
+    pub read: bool,
+    pub write: bool,
+    pub execute: bool,
+}
+
+impl Permissions {
+    pub const NONE: Permissions = Permissions { read: false, write: false, execute: false };
+    pub const R: Permissions = Permissions { read: true, write: false, execute: false };
+    pub const RW: Permissions = Permissions { read: true, write: true, execute: false };
+    pub const RX: Permissions = Permissions { read: true, write: false, execute: true };
+    pub const RWX: Permissions = Permissions { read: true, write: true, execute: true };
+
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+}
+
+pub struct Region {
+    pub first: u16,
+    pub last: u16,
+    pub permissions: Permissions,
+}
+
+/// Access permissions for address ranges. Data accesses are checked against the data address,
+/// instruction fetches against the pc. Addresses outside of all regions allow every access.
+#[derive(Default)]
+pub struct Protection {
+    pub regions: Vec<Region>,
+}
+
+impl Protection {
+    /// Sets the permissions of `first..=last`; regions added later take precedence where they overlap.
+    pub fn add(&mut self, first: u16, last: u16, permissions: Permissions) {
+        self.regions.push(Region { first, last, permissions });
+    }
+
+    pub fn allows(&self, addr: u16, access: Access) -> bool {
+        match self.regions.iter().rev().find(|r| r.first <= addr && addr <= r.last) {
+            Some(region) => region.permissions.allows(access),
+            None => true,
+        }
+    }
+}
@@ -0,0 +1,262 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use bus::Device;
+
+/// First address of the screen window, one cell per character, row by row.
+pub const SCREEN_BASE: u16 = 0xf000;
+/// Column of the text cursor.
+pub const SCREEN_CURSOR_X: u16 = 0xef00;
+/// Row of the text cursor.
+pub const SCREEN_CURSOR_Y: u16 = 0xef01;
+/// Writing a character here prints it at the cursor position and advances the cursor.
+pub const SCREEN_CHAR_OUT: u16 = 0xef02;
+/// Attributes given to characters printed through `SCREEN_CHAR_OUT`.
+pub const SCREEN_ATTR: u16 = 0xef03;
+
+/*
+ * Each screen cell holds a character and its attributes:
+ *
+ *   bit  0..6   character (7-bit ASCII)
+ *   bit  7      reverse video
+ *   bit  8..10  foreground colour
+ *   bit 11..13  background colour
+ *   bit 14      bold
+ *   bit 15      underline
+ */
+pub const CHAR_MASK: u16 = 0x007f;
+pub const ATTR_REVERSE: u16 = 0x0080;
+pub const ATTR_BOLD: u16 = 0x4000;
+pub const ATTR_UNDERLINE: u16 = 0x8000;
+pub const FG_SHIFT: u16 = 8;
+pub const BG_SHIFT: u16 = 11;
+
+pub const BLACK: u16 = 0;
+pub const RED: u16 = 1;
+pub const GREEN: u16 = 2;
+pub const YELLOW: u16 = 3;
+pub const BLUE: u16 = 4;
+pub const MAGENTA: u16 = 5;
+pub const CYAN: u16 = 6;
+pub const WHITE: u16 = 7;
+
+/// Light grey on black, without any further attributes.
+pub const DEFAULT_ATTR: u16 = WHITE << FG_SHIFT | BLACK << BG_SHIFT;
+
+const HTML_COLORS: [&str; 8] = ["#000000", "#aa0000", "#00aa00", "#aa5500", "#0000aa", "#aa00aa", "#00aaaa", "#aaaaaa"];
+
+pub const BACKSPACE: u8 = 0x08;
+pub const NEWLINE: u8 = 0x0a;
+/// Clears the screen and moves the cursor to the top left corner.
+pub const CLEAR: u8 = 0x0c;
+pub const CARRIAGE_RETURN: u8 = 0x0d;
+
+const BLANK: u16 = 0x20;
+
+pub struct Screen {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<u16>,
+    pub cursor_x: usize,
+    pub cursor_y: usize,
+    /// Attributes of characters printed at the cursor position.
+    pub attr: u16,
+    /// Set whenever the contents or the cursor change, reset by the renderer.
+    pub dirty: bool,
+}
+
+impl Screen {
+    pub fn new(width: usize, height: usize) -> Self {
+        Screen {
+            width,
+            height,
+            cells: vec![DEFAULT_ATTR | BLANK; width * height],
+            cursor_x: 0,
+            cursor_y: 0,
+            attr: DEFAULT_ATTR,
+            dirty: true,
+        }
+    }
+
+    /// Last address of the screen window.
+    pub fn last_addr(&self) -> u16 {
+        SCREEN_BASE + (self.cells.len() - 1) as u16
+    }
+
+    /// Prints a character at the cursor position, interpreting control characters.
+    pub fn put_char(&mut self, c: u8) {
+        match c {
+            NEWLINE => self.newline(),
+            CARRIAGE_RETURN => self.cursor_x = 0,
+            BACKSPACE => {
+                if self.cursor_x > 0 {
+                    self.cursor_x -= 1;
+                }
+                else if self.cursor_y > 0 {
+                    self.cursor_y -= 1;
+                    self.cursor_x = self.width - 1;
+                }
+                self.cells[self.cursor_y * self.width + self.cursor_x] = self.attr | BLANK;
+            },
+            CLEAR => self.clear(),
+            _ => {
+                self.cells[self.cursor_y * self.width + self.cursor_x] = self.attr | (c as u16 & CHAR_MASK);
+                self.cursor_x += 1;
+                if self.cursor_x == self.width {
+                    self.newline();
+                }
+            },
+        }
+        self.dirty = true;
+    }
+
+    pub fn clear(&mut self) {
+        let blank = self.attr | BLANK;
+        self.cells.iter_mut().for_each(|c| *c = blank);
+        self.cursor_x = 0;
+        self.cursor_y = 0;
+        self.dirty = true;
+    }
+
+    fn newline(&mut self) {
+        self.cursor_x = 0;
+        if self.cursor_y + 1 < self.height {
+            self.cursor_y += 1;
+        }
+        else {
+            self.scroll();
+        }
+    }
+
+    /// Moves all rows up by one and blanks the bottom row.
+    pub fn scroll(&mut self) {
+        self.cells.drain(0..self.width);
+        self.cells.resize(self.width * self.height, self.attr | BLANK);
+        self.dirty = true;
+    }
+
+    /// Renders the screen for an ANSI terminal, leaving the terminal cursor at the text cursor.
+    pub fn to_ansi(&self) -> String {
+        let mut out = String::from("\x1b[H");
+        for row in self.cells.chunks(self.width) {
+            let mut attr = None;
+            for cell in row {
+                if attr != Some(cell & !CHAR_MASK) {
+                    attr = Some(cell & !CHAR_MASK);
+                    out.push_str(&sgr(*cell));
+                }
+                out.push(printable(*cell));
+            }
+            out.push_str("\x1b[0m\r\n");
+        }
+        out.push_str(&format!("\x1b[{};{}H", self.cursor_y + 1, self.cursor_x + 1));
+        out
+    }
+
+    /// The characters on the screen as plain text, one line per row.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        for row in self.cells.chunks(self.width) {
+            out.extend(row.iter().map(|c| printable(*c)));
+            out.push('\n');
+        }
+        out
+    }
+
+    /// The screen as an HTML `pre` element, with colours and attributes given as inline styles.
+    pub fn to_html(&self) -> String {
+        let mut out = String::from("<pre style=\"font-family: monospace\">");
+        for row in self.cells.chunks(self.width) {
+            let mut attr = None;
+            for cell in row {
+                if attr != Some(cell & !CHAR_MASK) {
+                    if attr.is_some() {
+                        out.push_str("</span>");
+                    }
+                    attr = Some(cell & !CHAR_MASK);
+                    out.push_str(&format!("<span style=\"{}\">", css(*cell)));
+                }
+                match printable(*cell) {
+                    '<' => out.push_str("&lt;"),
+                    '>' => out.push_str("&gt;"),
+                    '&' => out.push_str("&amp;"),
+                    c => out.push(c),
+                }
+            }
+            out.push_str("</span>\n");
+        }
+        out.push_str("</pre>\n");
+        out
+    }
+}
+
+/// The screen is attached to the bus twice: its registers at `SCREEN_CURSOR_X..=SCREEN_ATTR`
+/// and its window at `SCREEN_BASE..=last_addr()`.
+impl Device for Screen {
+    fn read(&mut self, addr: u16) -> i16 {
+        match addr {
+            SCREEN_CURSOR_X => self.cursor_x as i16,
+            SCREEN_CURSOR_Y => self.cursor_y as i16,
+            SCREEN_CHAR_OUT => 0,
+            SCREEN_ATTR => self.attr as i16,
+            _ => self.cells[(addr - SCREEN_BASE) as usize] as i16,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: i16) {
+        match addr {
+            SCREEN_CURSOR_X => self.cursor_x = (value as u16 as usize).min(self.width - 1),
+            SCREEN_CURSOR_Y => self.cursor_y = (value as u16 as usize).min(self.height - 1),
+            SCREEN_CHAR_OUT => self.put_char(value as u8),
+            SCREEN_ATTR => self.attr = value as u16 & !CHAR_MASK,
+            _ => self.cells[(addr - SCREEN_BASE) as usize] = value as u16,
+        }
+        self.dirty = true;
+    }
+}
+
+fn printable(cell: u16) -> char {
+    match (cell & CHAR_MASK) as u8 {
+        c @ 0x20..=0x7e => c as char,
+        _ => ' ',
+    }
+}
+
+fn colors(cell: u16) -> (u16, u16) {
+    let fg = cell >> FG_SHIFT & 7;
+    let bg = cell >> BG_SHIFT & 7;
+    if cell & ATTR_REVERSE != 0 {
+        (bg, fg)
+    }
+    else {
+        (fg, bg)
+    }
+}
+
+/// ANSI select graphic rendition sequence for the attributes of `cell`.
+fn sgr(cell: u16) -> String {
+    let (fg, bg) = colors(cell);
+    let mut codes = format!("\x1b[0;{};{}", 30 + fg, 40 + bg);
+    if cell & ATTR_BOLD != 0 {
+        codes.push_str(";1");
+    }
+    if cell & ATTR_UNDERLINE != 0 {
+        codes.push_str(";4");
+    }
+    codes.push('m');
+    codes
+}
+
+fn css(cell: u16) -> String {
+    let (fg, bg) = colors(cell);
+    let mut style = format!("color: {}; background-color: {}", HTML_COLORS[fg as usize], HTML_COLORS[bg as usize]);
+    if cell & ATTR_BOLD != 0 {
+        style.push_str("; font-weight: bold");
+    }
+    if cell & ATTR_UNDERLINE != 0 {
+        style.push_str("; text-decoration: underline");
+    }
+    style
+}
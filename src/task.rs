@@ -0,0 +1,174 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use std::mem;
+
+use console::{BufferConsole, Console};
+use error::Error;
+use flags::Flags;
+use memory::MemoryModel;
+use privilege::Mode;
+use timer::Timer;
+use {read_object, Machine, Registers, State, SP};
+
+/// How a task ended.
+#[derive(Debug)]
+pub enum Exit {
+    /// The task executed `HALT`, with this value in r0.
+    Halted(i16),
+    /// An instruction of the task failed.
+    Failed(Error),
+}
+
+/// Everything a task does not share with the other tasks, including its interrupt state and timer.
+/// While the task runs, its context is swapped into the machine; the other devices stay shared.
+struct Context {
+    r: Registers,
+    pc: usize,
+    flags: Flags,
+    mode: Mode,
+    stack: Vec<State>,
+    code: Vec<u8>,
+    /// The data memory in the Harvard model; the von Neumann memory is `code`.
+    cells: Vec<i16>,
+    console: Box<dyn Console>,
+    interrupts_enabled: bool,
+    pending_irqs: u16,
+    waiting: bool,
+    timer: Timer,
+}
+
+impl Context {
+    /// Swaps the context with the machine's, so calling it twice restores both.
+    fn exchange(&mut self, m: &mut Machine) {
+        mem::swap(&mut self.r, &mut m.r);
+        mem::swap(&mut self.pc, &mut m.pc);
+        mem::swap(&mut self.flags, &mut m.flags);
+        mem::swap(&mut self.mode, &mut m.mode);
+        mem::swap(&mut self.stack, &mut m.stack);
+        mem::swap(&mut self.code, &mut m.code);
+        if let Some(ram) = &m.ram {
+            mem::swap(&mut self.cells, &mut ram.borrow_mut().cells);
+        }
+        mem::swap(&mut self.console, &mut m.console);
+        mem::swap(&mut self.interrupts_enabled, &mut m.interrupts_enabled);
+        mem::swap(&mut self.pending_irqs, &mut m.pending_irqs);
+        mem::swap(&mut self.waiting, &mut m.waiting);
+        mem::swap(&mut self.timer, &mut m.timer.borrow_mut());
+    }
+}
+
+pub struct Task {
+    context: Context,
+    output: BufferConsole,
+    /// `None` while the task can still run.
+    pub exit: Option<Exit>,
+}
+
+impl Task {
+    /// What the task wrote to its console.
+    pub fn output(&self) -> String {
+        self.output.output_string()
+    }
+}
+
+/// Runs several programs in one machine, round robin. Each task has its own registers, pc,
+/// call stack, memory and console output, and runs until it executes `YIELD`, halts, fails
+/// or, with a time slice set, has executed that many instructions.
+pub struct Scheduler {
+    pub machine: Machine,
+    pub tasks: Vec<Task>,
+    /// Number of instructions after which a task is preempted, if any.
+    pub time_slice: Option<usize>,
+    next: usize,
+}
+
+impl Scheduler {
+    pub fn new(machine: Machine) -> Self {
+        Scheduler {
+            machine,
+            tasks: Vec::new(),
+            time_slice: None,
+            next: 0,
+        }
+    }
+
+    /// Adds a task running `obj` from address 0 with empty memory, and returns its index.
+    pub fn spawn(&mut self, obj: &[u8]) -> Result<usize, Error> {
+        let m = &self.machine;
+        let (code, cells) = match m.memory_model {
+            MemoryModel::Harvard => (obj.to_vec(), vec![0; m.ram.as_ref().map_or(0, |ram| ram.borrow().cells.len())]),
+            MemoryModel::VonNeumann => {
+                if obj.len() > m.code.len() {
+                    return Err(Error::ObjectFileTooLarge(obj.len()));
+                }
+                let mut memory = vec![0; m.code.len()];
+                memory[..obj.len()].copy_from_slice(obj);
+                (memory, Vec::new())
+            },
+        };
+        let mut r: Registers = [0; 16];
+        r[SP] = m.stack_top as i16;
+        let output = BufferConsole::default();
+        self.tasks.push(Task {
+            context: Context {
+                r,
+                pc: 0,
+                flags: Flags::default(),
+                mode: Mode::default(),
+                stack: Vec::new(),
+                code,
+                cells,
+                console: Box::new(output.clone()),
+                interrupts_enabled: false,
+                pending_irqs: 0,
+                waiting: false,
+                timer: Timer::default(),
+            },
+            output,
+            exit: None,
+        });
+        Ok(self.tasks.len() - 1)
+    }
+
+    /// Adds a task running the object file `filename`.
+    pub fn load(&mut self, filename: &String) -> Result<usize, Error> {
+        self.spawn(&read_object(filename)?)
+    }
+
+    /// Runs the tasks until all of them have halted or failed.
+    pub fn run(&mut self) {
+        while let Some(idx) = self.next_ready() {
+            self.tasks[idx].context.exchange(&mut self.machine);
+            let exit = self.run_slice();
+            self.tasks[idx].context.exchange(&mut self.machine);
+            self.tasks[idx].exit = exit;
+            self.next = idx + 1;
+        }
+    }
+
+    /// The first task from `next` on, wrapping around, that can still run.
+    fn next_ready(&self) -> Option<usize> {
+        let n = self.tasks.len();
+        (0..n).map(|i| (self.next + i) % n).find(|&i| self.tasks[i].exit.is_none())
+    }
+
+    /// Runs the task swapped into the machine until it yields, is preempted or ends.
+    fn run_slice(&mut self) -> Option<Exit> {
+        self.machine.yielded = false;
+        let mut steps = 0;
+        loop {
+            match self.machine.step() {
+                Ok(true) => (),
+                Ok(false) => return Some(Exit::Halted(self.machine.r[0])),
+                Err(e) => return Some(Exit::Failed(e)),
+            }
+            steps += 1;
+            if self.machine.yielded || self.time_slice.is_some_and(|n| steps >= n) {
+                return None;
+            }
+        }
+    }
+}
@@ -0,0 +1,57 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use bus::Device;
+
+/// Number of steps between two timer interrupts; 0 stops the timer.
+pub const TIMER_PERIOD: u16 = 0xef10;
+/// Steps left until the next timer interrupt.
+pub const TIMER_COUNT: u16 = 0xef11;
+/// Interrupt line of the timer.
+pub const IRQ_TIMER: u8 = 0;
+
+/// Programmable interval timer raising an interrupt every `period` steps.
+#[derive(Default)]
+pub struct Timer {
+    pub period: u16,
+    pub count: u16,
+    fired: bool,
+}
+
+impl Device for Timer {
+    fn read(&mut self, addr: u16) -> i16 {
+        match addr {
+            TIMER_PERIOD => self.period as i16,
+            _ => self.count as i16,
+        }
+    }
+
+    fn write(&mut self, addr: u16, value: i16) {
+        if addr == TIMER_PERIOD {
+            self.period = value as u16;
+            self.count = self.period;
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.period == 0 {
+            return;
+        }
+        self.count = self.count.saturating_sub(1);
+        if self.count == 0 {
+            self.count = self.period;
+            self.fired = true;
+        }
+    }
+
+    fn interrupt(&mut self) -> Option<u8> {
+        if std::mem::replace(&mut self.fired, false) {
+            Some(IRQ_TIMER)
+        }
+        else {
+            None
+        }
+    }
+}
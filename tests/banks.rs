@@ -0,0 +1,59 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use riscvm::{Machine, MachineBuilder};
+use riscvm::bank::{BANK_COUNT, BANK_SELECT};
+use riscvm::opcode::Opcode;
+
+fn banked_machine() -> Machine {
+    MachineBuilder::new().banked_memory(0x8000, 0x100, 4).build().unwrap()
+}
+
+#[test]
+fn bank_register_selects_the_window_contents() {
+    let mut m = banked_machine();
+    // cp r1, #2 ; cp [BANK_SELECT], r1 ; cp r2, #42 ; cp [$8010], r2
+    let mut code = vec![Opcode::CpRImm as u8, 1, 2, 0, Opcode::CpMemR as u8];
+    code.extend_from_slice(&BANK_SELECT.to_le_bytes());
+    code.extend_from_slice(&[1, Opcode::CpRImm as u8, 2, 42, 0, Opcode::CpMemR as u8, 0x10, 0x80, 2, Opcode::Halt as u8]);
+    m.code = code;
+    m.run().unwrap();
+    assert_eq!(m.read_mem(BANK_SELECT).unwrap(), 2);
+    assert_eq!(m.read_mem(BANK_COUNT).unwrap(), 4);
+    assert_eq!(m.read_mem(0x8010).unwrap(), 42);
+    m.write_mem(BANK_SELECT, 0).unwrap();
+    assert_eq!(m.read_mem(0x8010).unwrap(), 0);
+    assert_eq!(m.banked.as_ref().unwrap().borrow().page(2)[0x10], 42);
+}
+
+#[test]
+fn selecting_a_missing_bank_is_ignored() {
+    let mut m = banked_machine();
+    m.write_mem(BANK_SELECT, 1).unwrap();
+    m.write_mem(BANK_SELECT, 4).unwrap();
+    assert_eq!(m.read_mem(BANK_SELECT).unwrap(), 1);
+}
+
+#[test]
+fn dumps_show_the_bank() {
+    let mut m = banked_machine();
+    m.write_mem(BANK_SELECT, 3).unwrap();
+    m.write_mem(0x8001, 0x1234).unwrap();
+    m.write_mem(0x7ff8, -1).unwrap();
+    assert_eq!(
+        m.dump_memory(0x7ff8, 0x8007),
+        "7ff8: ffff 0000 0000 0000 0000 0000 0000 0000\n03:8000: 0000 1234 0000 0000 0000 0000 0000 0000\n"
+    );
+    assert!(m.dump_bank(3).unwrap().starts_with("03:8000: 0000 1234"));
+    assert!(m.dump_bank(0).unwrap().starts_with("00:8000: 0000 0000"));
+    assert!(m.dump_bank(4).is_none());
+}
+
+#[test]
+fn window_must_not_overlap_the_io_page() {
+    assert!(MachineBuilder::new().banked_memory(0xe000, 0x1000, 2).build().is_err());
+}
@@ -0,0 +1,43 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use riscvm::{Machine, MachineBuilder, SP};
+use riscvm::error::Error;
+use riscvm::opcode::Opcode;
+
+#[test]
+fn default_memory_covers_the_address_space() {
+    let mut m = Machine::new();
+    m.write_mem(0xfffe, 1).unwrap();
+    assert_eq!(m.read_mem(0xfffe).unwrap(), 1);
+    assert!(m.read_mem(0xffff).is_ok());
+}
+
+#[test]
+fn small_memory() {
+    let mut m = MachineBuilder::new().memory_size(0x1000).build().unwrap();
+    assert_eq!(m.r[SP], 0x1000);
+    assert!(m.write_mem(0x0fff, 1).is_ok());
+    assert!(matches!(m.write_mem(0x1000, 1), Err(Error::InvalidAddress(0x1000, _))));
+}
+
+#[test]
+fn stack_depth() {
+    let mut m = MachineBuilder::new().stack_depth(2).build().unwrap();
+    m.code = vec![Opcode::CallS as u8, 0, 0];
+    assert!(m.step().is_ok());
+    assert!(m.step().is_ok());
+    assert!(matches!(m.step(), Err(Error::StackOverflow)));
+}
+
+#[test]
+fn screen_size() {
+    let m = MachineBuilder::new().screen_size(40, 12).build().unwrap();
+    assert_eq!(m.screen_text().len(), 12);
+    assert!(m.read_mem(0xf000 + 40 * 12 - 1).is_ok());
+    assert!(MachineBuilder::new().screen_size(132, 50).build().is_err());
+}
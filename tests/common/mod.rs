@@ -0,0 +1,38 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use riscvm::Machine;
+
+/// Compares the screen of `m` with the golden file `tests/golden/<name>.txt`
+/// and fails with a line diff if they differ.
+///
+/// Set `UPDATE_GOLDEN=1` to (re)write the golden file from the current screen instead.
+pub fn assert_screen_matches(m: &Machine, name: &str) {
+    let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "tests", "golden", &format!("{}.txt", name)].iter().collect();
+    let actual = m.screen_text();
+    if env::var("UPDATE_GOLDEN").is_ok() {
+        fs::write(&path, actual.join("\n") + "\n").expect("cannot write golden file");
+        return;
+    }
+    let expected = fs::read_to_string(&path)
+        .unwrap_or_else(|e| panic!("cannot read golden file {}: {}", path.display(), e));
+    let expected: Vec<&str> = expected.lines().collect();
+    let mut diff = String::new();
+    for i in 0..expected.len().max(actual.len()) {
+        let e = expected.get(i).copied();
+        let a = actual.get(i).map(|line| line.as_str());
+        if e != a {
+            diff.push_str(&format!("{:3} - {}\n", i + 1, e.unwrap_or("<missing>")));
+            diff.push_str(&format!("{:3} + {}\n", i + 1, a.unwrap_or("<missing>")));
+        }
+    }
+    if !diff.is_empty() {
+        panic!("screen differs from {}:\n{}", path.display(), diff);
+    }
+}
@@ -0,0 +1,14 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+//! The instruction conformance tests with separate code and data memory.
+
+extern crate riscvm;
+
+use riscvm::memory::MemoryModel;
+
+const MODEL: MemoryModel = MemoryModel::Harvard;
+
+include!("conformance/cases.rs");
@@ -0,0 +1,1262 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+// One test per row of the instruction table in `doc/murx.tex`, included by one test crate
+// per memory model with `MODEL` set accordingly.
+
+use riscvm::{Machine, MachineBuilder, IRQ_KEYBOARD, SP, STACK_TOP};
+use riscvm::abi::RetsRestores;
+use riscvm::console::BufferConsole;
+use riscvm::exception::EXC_SYSCALL;
+use riscvm::opcode::Opcode;
+use riscvm::privilege::Mode;
+use riscvm::timer::{IRQ_TIMER, TIMER_PERIOD};
+
+fn rr(op: Opcode, rd: u8, rs: u8) -> Vec<u8> {
+    vec![op as u8, rd << 4 | rs]
+}
+
+fn r(op: Opcode, rd: u8) -> Vec<u8> {
+    vec![op as u8, rd]
+}
+
+fn ri(op: Opcode, rd: u8, imm: i16) -> Vec<u8> {
+    let mut code = vec![op as u8, rd];
+    code.extend_from_slice(&imm.to_le_bytes());
+    code
+}
+
+fn rm(op: Opcode, rd: u8, addr: u16) -> Vec<u8> {
+    let mut code = vec![op as u8, rd];
+    code.extend_from_slice(&addr.to_le_bytes());
+    code
+}
+
+fn rx(op: Opcode, rd: u8, ra: u8, offset: i16) -> Vec<u8> {
+    let mut code = vec![op as u8, rd << 4 | ra];
+    code.extend_from_slice(&offset.to_le_bytes());
+    code
+}
+
+fn a(op: Opcode, addr: u16) -> Vec<u8> {
+    let mut code = vec![op as u8];
+    code.extend_from_slice(&addr.to_le_bytes());
+    code
+}
+
+fn machine() -> Machine {
+    MachineBuilder::new().memory_model(MODEL).build().unwrap()
+}
+
+/// Address distance between consecutive data words.
+fn word() -> u16 {
+    MODEL.word_size()
+}
+
+/// Runs `code` followed by `HALT` on a machine prepared by `setup`.
+fn exec<F: FnOnce(&mut Machine)>(mut code: Vec<u8>, setup: F) -> Machine {
+    let mut m = machine();
+    code.push(Opcode::Halt as u8);
+    m.load_code(&code).unwrap();
+    setup(&mut m);
+    m.run().expect("program failed");
+    m
+}
+
+/// Runs a single instruction with r1 = `a` and r2 = `b`.
+fn binary(code: Vec<u8>, a: i16, b: i16) -> Machine {
+    exec(code, |m| {
+        m.r[1] = a;
+        m.r[2] = b;
+        m.write_mem(0x100, b).unwrap();
+    })
+}
+
+/// Compares `a` with `b`, then reports whether the branch was taken.
+fn branch(op: Opcode, a: i16, b: i16) -> bool {
+    let mut code = rr(Opcode::CmpRR, 1, 2);
+    code.extend(self::a(op, 10));
+    code.extend(ri(Opcode::CpRImm, 0, 1));
+    code.push(Opcode::Halt as u8);
+    code.extend(ri(Opcode::CpRImm, 0, 2));
+    let m = binary(code, a, b);
+    m.r[0] == 2
+}
+
+#[test]
+fn mv_r_r() {
+    let m = binary(rr(Opcode::CpRR, 1, 2), 0, 42);
+    assert_eq!(m.r[1], 42);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn mv_r_imm() {
+    let m = exec(ri(Opcode::CpRImm, 3, -1234), |_| ());
+    assert_eq!(m.r[3], -1234);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn mv_r_mem() {
+    let m = exec(rm(Opcode::CpRMem, 3, 0x1234), |m| m.write_mem(0x1234, 99).unwrap());
+    assert_eq!(m.r[3], 99);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn mv_mem_r() {
+    let mut code = a(Opcode::CpMemR, 0x1234);
+    code.push(5);
+    let m = exec(code, |m| m.r[5] = 77);
+    assert_eq!(m.read_mem(0x1234).unwrap(), 77);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn mv_r_ind() {
+    let m = exec(rr(Opcode::CpRInd, 1, 2), |m| {
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 11).unwrap();
+    });
+    assert_eq!(m.r[1], 11);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn mv_ind_r() {
+    let m = exec(rr(Opcode::CpIndR, 2, 1), |m| {
+        m.r[1] = 11;
+        m.r[2] = 0x200;
+    });
+    assert_eq!(m.read_mem(0x200).unwrap(), 11);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn mv_r_idx() {
+    let m = exec(rx(Opcode::CpRIdx, 1, 2, -2), |m| {
+        m.r[2] = 0x202;
+        m.write_mem(0x200, 11).unwrap();
+    });
+    assert_eq!(m.r[1], 11);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn mv_idx_r() {
+    let m = exec(rx(Opcode::CpIdxR, 2, 1, 3), |m| {
+        m.r[1] = 11;
+        m.r[2] = 0x200;
+    });
+    assert_eq!(m.read_mem(0x203).unwrap(), 11);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn mv_r_ind_inc() {
+    let m = exec(rr(Opcode::CpRIndInc, 1, 2), |m| {
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 11).unwrap();
+    });
+    assert_eq!(m.r[1], 11);
+    assert_eq!(m.r[2], (0x200 + word()) as i16);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn mv_ind_inc_r() {
+    let m = exec(rr(Opcode::CpIndIncR, 2, 1), |m| {
+        m.r[1] = 11;
+        m.r[2] = 0x200;
+    });
+    assert_eq!(m.read_mem(0x200).unwrap(), 11);
+    assert_eq!(m.r[2], (0x200 + word()) as i16);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn xchg_r_r() {
+    let m = binary(rr(Opcode::XchgRR, 1, 2), 3, 4);
+    assert_eq!((m.r[1], m.r[2]), (4, 3));
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn xchg_r_mem() {
+    let m = binary(rm(Opcode::XchgRMem, 1, 0x100), 3, 4);
+    assert_eq!((m.r[1], m.read_mem(0x100).unwrap()), (4, 3));
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn add_r_r() {
+    let m = binary(rr(Opcode::AddRR, 1, 2), 3, 4);
+    assert_eq!(m.r[1], 7);
+    let m = binary(rr(Opcode::AddRR, 1, 2), i16::MAX, 1);
+    assert_eq!(m.r[1], i16::MIN);
+    assert!(m.flags.v && !m.flags.c && m.flags.n);
+    let m = binary(rr(Opcode::AddRR, 1, 2), -1, 1);
+    assert!(m.flags.z && m.flags.c && !m.flags.v);
+}
+
+#[test]
+fn add_r_imm() {
+    let m = binary(ri(Opcode::AddRImm, 1, 40), 2, 0);
+    assert_eq!(m.r[1], 42);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn add_r_mem() {
+    let m = binary(rm(Opcode::AddRMem, 1, 0x100), 2, 40);
+    assert_eq!(m.r[1], 42);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn add_r_ind() {
+    let m = exec(rr(Opcode::AddRInd, 1, 2), |m| {
+        m.r[1] = 2;
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 40).unwrap();
+    });
+    assert_eq!(m.r[1], 42);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn add_r_idx() {
+    let m = exec(rx(Opcode::AddRIdx, 1, 2, 1), |m| {
+        m.r[1] = 2;
+        m.r[2] = 0x200;
+        m.write_mem(0x201, 40).unwrap();
+    });
+    assert_eq!(m.r[1], 42);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn sub_r_r() {
+    let m = binary(rr(Opcode::SubRR, 1, 2), 3, 4);
+    assert_eq!(m.r[1], -1);
+    assert!(m.flags.c && m.flags.n && !m.flags.v);
+    let m = binary(rr(Opcode::SubRR, 1, 2), i16::MIN, 1);
+    assert!(m.flags.v && !m.flags.c);
+}
+
+#[test]
+fn sub_r_imm() {
+    let m = binary(ri(Opcode::SubRImm, 1, 2), 44, 0);
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn sub_r_mem() {
+    let m = binary(rm(Opcode::SubRMem, 1, 0x100), 44, 2);
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn sub_r_ind() {
+    let m = exec(rr(Opcode::SubRInd, 1, 2), |m| {
+        m.r[1] = 44;
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 2).unwrap();
+    });
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn sub_r_idx() {
+    let m = exec(rx(Opcode::SubRIdx, 1, 2, 1), |m| {
+        m.r[1] = 44;
+        m.r[2] = 0x200;
+        m.write_mem(0x201, 2).unwrap();
+    });
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn adc_r_r() {
+    let m = exec(rr(Opcode::AdcRR, 1, 2), |m| {
+        m.r[1] = 3;
+        m.r[2] = 4;
+        m.flags.c = true;
+    });
+    assert_eq!(m.r[1], 8);
+}
+
+#[test]
+fn adc_r_imm() {
+    let m = exec(ri(Opcode::AdcRImm, 1, 1), |m| {
+        m.r[1] = -1;
+        m.flags.c = true;
+    });
+    assert_eq!(m.r[1], 1);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn adc_r_mem() {
+    let m = exec(rm(Opcode::AdcRMem, 1, 0x100), |m| {
+        m.r[1] = 3;
+        m.write_mem(0x100, 4).unwrap();
+    });
+    assert_eq!(m.r[1], 7);
+}
+
+#[test]
+fn sbc_r_r() {
+    let m = exec(rr(Opcode::SbcRR, 1, 2), |m| {
+        m.r[1] = 10;
+        m.r[2] = 4;
+        m.flags.c = true;
+    });
+    assert_eq!(m.r[1], 5);
+}
+
+#[test]
+fn sbc_r_imm() {
+    let m = exec(ri(Opcode::SbcRImm, 1, 0), |m| {
+        m.r[1] = 0;
+        m.flags.c = true;
+    });
+    assert_eq!(m.r[1], -1);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn sbc_r_mem() {
+    let m = exec(rm(Opcode::SbcRMem, 1, 0x100), |m| {
+        m.r[1] = 10;
+        m.write_mem(0x100, 4).unwrap();
+    });
+    assert_eq!(m.r[1], 6);
+}
+
+#[test]
+fn mul_r_r() {
+    let m = binary(rr(Opcode::MulRR, 1, 2), 6, -7);
+    assert_eq!(m.r[1], -42);
+    let m = binary(rr(Opcode::MulRR, 1, 2), 300, 300);
+    assert!(m.flags.v && m.flags.c);
+}
+
+#[test]
+fn mul_r_imm() {
+    let m = binary(ri(Opcode::MulRImm, 1, 7), 6, 0);
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn mul_r_mem() {
+    let m = binary(rm(Opcode::MulRMem, 1, 0x100), 6, 7);
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn mul_r_ind() {
+    let m = exec(rr(Opcode::MulRInd, 1, 2), |m| {
+        m.r[1] = 6;
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 7).unwrap();
+    });
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn mul_r_idx() {
+    let m = exec(rx(Opcode::MulRIdx, 1, 2, 1), |m| {
+        m.r[1] = 6;
+        m.r[2] = 0x200;
+        m.write_mem(0x201, 7).unwrap();
+    });
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn div_r_r() {
+    let m = binary(rr(Opcode::DivRR, 1, 2), -85, 2);
+    assert_eq!(m.r[1], -42);
+    let m = binary(rr(Opcode::DivRR, 1, 2), i16::MIN, -1);
+    assert_eq!(m.r[1], i16::MIN);
+    assert!(m.flags.v);
+    let mut m = machine();
+    m.load_code(&rr(Opcode::DivRR, 1, 2)).unwrap();
+    assert!(m.run().is_err());
+}
+
+#[test]
+fn div_r_imm() {
+    let m = binary(ri(Opcode::DivRImm, 1, 2), 84, 0);
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn div_r_mem() {
+    let m = binary(rm(Opcode::DivRMem, 1, 0x100), 84, 2);
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn div_r_ind() {
+    let m = exec(rr(Opcode::DivRInd, 1, 2), |m| {
+        m.r[1] = 84;
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 2).unwrap();
+    });
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn div_r_idx() {
+    let m = exec(rx(Opcode::DivRIdx, 1, 2, 1), |m| {
+        m.r[1] = 84;
+        m.r[2] = 0x200;
+        m.write_mem(0x201, 2).unwrap();
+    });
+    assert_eq!(m.r[1], 42);
+}
+
+#[test]
+fn mod_r_r() {
+    let m = binary(rr(Opcode::ModRR, 1, 2), -7, 3);
+    assert_eq!(m.r[1], -1);
+}
+
+#[test]
+fn mod_r_imm() {
+    let m = binary(ri(Opcode::ModRImm, 1, 5), 47, 0);
+    assert_eq!(m.r[1], 2);
+}
+
+#[test]
+fn mod_r_mem() {
+    let m = binary(rm(Opcode::ModRMem, 1, 0x100), 47, 5);
+    assert_eq!(m.r[1], 2);
+}
+
+#[test]
+fn mulw_r_r() {
+    let m = binary(rr(Opcode::MulWRR, 1, 2), -300, 300);
+    assert_eq!((m.r[2] as i32) << 16 | m.r[1] as u16 as i32, -90000);
+}
+
+#[test]
+fn divw_r_r() {
+    let m = exec(rr(Opcode::DivWRR, 1, 3), |m| {
+        m.r[1] = 90001u32 as u16 as i16;
+        m.r[2] = (90001u32 >> 16) as i16;
+        m.r[3] = 300;
+    });
+    assert_eq!((m.r[1], m.r[2]), (300, 1));
+}
+
+#[test]
+fn xor_r_r() {
+    let m = binary(rr(Opcode::XorRR, 1, 2), 0b1100, 0b1010);
+    assert_eq!(m.r[1], 0b0110);
+}
+
+#[test]
+fn xor_r_imm() {
+    let m = binary(ri(Opcode::XorRImm, 1, -1), 0, 0);
+    assert_eq!(m.r[1], -1);
+    assert!(m.flags.n && !m.flags.z);
+}
+
+#[test]
+fn xor_r_mem() {
+    let m = binary(rm(Opcode::XorRMem, 1, 0x100), 5, 5);
+    assert_eq!(m.r[1], 0);
+    assert!(m.flags.z);
+}
+
+#[test]
+fn xor_r_ind() {
+    let m = exec(rr(Opcode::XorRInd, 1, 2), |m| {
+        m.r[1] = 0b1100;
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 0b1010).unwrap();
+    });
+    assert_eq!(m.r[1], 0b0110);
+}
+
+#[test]
+fn xor_r_idx() {
+    let m = exec(rx(Opcode::XorRIdx, 1, 2, 1), |m| {
+        m.r[1] = 0b1100;
+        m.r[2] = 0x200;
+        m.write_mem(0x201, 0b1010).unwrap();
+    });
+    assert_eq!(m.r[1], 0b0110);
+}
+
+#[test]
+fn and_r_r() {
+    let m = binary(rr(Opcode::AndRR, 1, 2), 0b1100, 0b1010);
+    assert_eq!(m.r[1], 0b1000);
+}
+
+#[test]
+fn and_r_imm() {
+    let m = binary(ri(Opcode::AndRImm, 1, 0x00ff), 0x1234, 0);
+    assert_eq!(m.r[1], 0x34);
+}
+
+#[test]
+fn and_r_mem() {
+    let m = binary(rm(Opcode::AndRMem, 1, 0x100), 0b1100, 0b1010);
+    assert_eq!(m.r[1], 0b1000);
+}
+
+#[test]
+fn and_r_ind() {
+    let m = exec(rr(Opcode::AndRInd, 1, 2), |m| {
+        m.r[1] = 0b1100;
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 0b1010).unwrap();
+    });
+    assert_eq!(m.r[1], 0b1000);
+}
+
+#[test]
+fn and_r_idx() {
+    let m = exec(rx(Opcode::AndRIdx, 1, 2, 1), |m| {
+        m.r[1] = 0b1100;
+        m.r[2] = 0x200;
+        m.write_mem(0x201, 0b1010).unwrap();
+    });
+    assert_eq!(m.r[1], 0b1000);
+}
+
+#[test]
+fn or_r_r() {
+    let m = binary(rr(Opcode::OrRR, 1, 2), 0b1100, 0b1010);
+    assert_eq!(m.r[1], 0b1110);
+}
+
+#[test]
+fn or_r_imm() {
+    let m = binary(ri(Opcode::OrRImm, 1, 0x0f00), 0x00f0, 0);
+    assert_eq!(m.r[1], 0x0ff0);
+}
+
+#[test]
+fn or_r_mem() {
+    let m = binary(rm(Opcode::OrRMem, 1, 0x100), 0b1100, 0b1010);
+    assert_eq!(m.r[1], 0b1110);
+}
+
+#[test]
+fn or_r_ind() {
+    let m = exec(rr(Opcode::OrRInd, 1, 2), |m| {
+        m.r[1] = 0b1100;
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 0b1010).unwrap();
+    });
+    assert_eq!(m.r[1], 0b1110);
+}
+
+#[test]
+fn or_r_idx() {
+    let m = exec(rx(Opcode::OrRIdx, 1, 2, 1), |m| {
+        m.r[1] = 0b1100;
+        m.r[2] = 0x200;
+        m.write_mem(0x201, 0b1010).unwrap();
+    });
+    assert_eq!(m.r[1], 0b1110);
+}
+
+#[test]
+fn neg_r() {
+    let m = binary(r(Opcode::NegR, 1), 42, 0);
+    assert_eq!(m.r[1], -42);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn neg_mem() {
+    let m = binary(a(Opcode::NegMem, 0x100), 0, 42);
+    assert_eq!(m.read_mem(0x100).unwrap(), -42);
+    assert_eq!(m.pc, 3);
+}
+
+#[test]
+fn not_r() {
+    let m = binary(r(Opcode::NotR, 1), 0, 0);
+    assert_eq!(m.r[1], -1);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn not_mem() {
+    let m = binary(a(Opcode::NotMem, 0x100), 0, -1);
+    assert_eq!(m.read_mem(0x100).unwrap(), 0);
+    assert!(m.flags.z);
+    assert_eq!(m.pc, 3);
+}
+
+#[test]
+fn shr_r_r() {
+    let m = binary(rr(Opcode::ShrRR, 1, 2), -2, 1);
+    assert_eq!(m.r[1], 0x7fff);
+    let m = binary(rr(Opcode::ShrRR, 1, 2), -1, -1);
+    assert_eq!(m.r[1], 0);
+}
+
+#[test]
+fn shr_r_imm() {
+    let m = binary(ri(Opcode::ShrRImm, 1, 2), 0b111, 0);
+    assert_eq!(m.r[1], 0b1);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn shl_r_r() {
+    let m = binary(rr(Opcode::ShlRR, 1, 2), 1, 15);
+    assert_eq!(m.r[1], i16::MIN);
+    let m = binary(rr(Opcode::ShlRR, 1, 2), 1, 16);
+    assert_eq!(m.r[1], 0);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn shl_r_imm() {
+    let m = binary(ri(Opcode::ShlRImm, 1, 4), 1, 0);
+    assert_eq!(m.r[1], 16);
+}
+
+#[test]
+fn sar_r_r() {
+    let m = binary(rr(Opcode::SarRR, 1, 2), -4, 1);
+    assert_eq!(m.r[1], -2);
+    let m = binary(rr(Opcode::SarRR, 1, 2), -4, 100);
+    assert_eq!(m.r[1], -1);
+}
+
+#[test]
+fn sar_r_imm() {
+    let m = binary(ri(Opcode::SarRImm, 1, 2), 0x4000, 0);
+    assert_eq!(m.r[1], 0x1000);
+}
+
+#[test]
+fn rol_r_r() {
+    let m = binary(rr(Opcode::RolRR, 1, 2), 0x4001, 2);
+    assert_eq!(m.r[1], 0x0005);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn rol_r_imm() {
+    let m = binary(ri(Opcode::RolRImm, 1, 20), 0x1234, 0);
+    assert_eq!(m.r[1], 0x2341);
+}
+
+#[test]
+fn ror_r_r() {
+    let m = binary(rr(Opcode::RorRR, 1, 2), 1, 1);
+    assert_eq!(m.r[1], i16::MIN);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn ror_r_imm() {
+    let m = binary(ri(Opcode::RorRImm, 1, 4), 0x1234, 0);
+    assert_eq!(m.r[1], 0x4123);
+}
+
+#[test]
+fn rcl_r_r() {
+    let m = exec(rr(Opcode::RclRR, 1, 2), |m| {
+        m.r[1] = i16::MIN;
+        m.r[2] = 1;
+        m.flags.c = true;
+    });
+    assert_eq!(m.r[1], 1);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn rcl_r_imm() {
+    let m = binary(ri(Opcode::RclRImm, 1, 17), 0x1234, 0);
+    assert_eq!(m.r[1], 0x1234);
+}
+
+#[test]
+fn rcr_r_r() {
+    let m = exec(rr(Opcode::RcrRR, 1, 2), |m| {
+        m.r[1] = 1;
+        m.r[2] = 1;
+        m.flags.c = true;
+    });
+    assert_eq!(m.r[1], i16::MIN);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn rcr_r_imm() {
+    let m = binary(ri(Opcode::RcrRImm, 1, 2), 0b110, 0);
+    assert_eq!(m.r[1], 0b1);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn bt_r_r() {
+    let m = binary(rr(Opcode::BtRR, 1, 2), 0b100, 2);
+    assert!(m.flags.c);
+    assert_eq!(m.r[1], 0b100);
+}
+
+#[test]
+fn bt_r_imm() {
+    let m = binary(ri(Opcode::BtRImm, 1, 1), 0b100, 0);
+    assert!(!m.flags.c);
+}
+
+#[test]
+fn bts_r_r() {
+    let m = binary(rr(Opcode::BtsRR, 1, 2), 0, 15);
+    assert_eq!(m.r[1], i16::MIN);
+    assert!(!m.flags.c);
+}
+
+#[test]
+fn bts_r_imm() {
+    let m = binary(ri(Opcode::BtsRImm, 1, 17), 0, 0);
+    assert_eq!(m.r[1], 0b10);
+}
+
+#[test]
+fn btr_r_r() {
+    let m = binary(rr(Opcode::BtrRR, 1, 2), 0b111, 1);
+    assert_eq!(m.r[1], 0b101);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn btr_r_imm() {
+    let m = binary(ri(Opcode::BtrRImm, 1, 0), 0b111, 0);
+    assert_eq!(m.r[1], 0b110);
+}
+
+#[test]
+fn btc_r_r() {
+    let m = binary(rr(Opcode::BtcRR, 1, 2), 0b101, 1);
+    assert_eq!(m.r[1], 0b111);
+    assert!(!m.flags.c);
+}
+
+#[test]
+fn btc_r_imm() {
+    let m = binary(ri(Opcode::BtcRImm, 1, 0), 0b101, 0);
+    assert_eq!(m.r[1], 0b100);
+    assert!(m.flags.c);
+}
+
+#[test]
+fn popcnt_r_r() {
+    let m = binary(rr(Opcode::PopCntRR, 1, 2), 0, -1);
+    assert_eq!(m.r[1], 16);
+}
+
+#[test]
+fn clz_r_r() {
+    let m = binary(rr(Opcode::ClzRR, 1, 2), 0, 0x00ff);
+    assert_eq!(m.r[1], 8);
+}
+
+#[test]
+fn bswap_r_r() {
+    let m = binary(rr(Opcode::BSwapRR, 1, 2), 0, 0x1234);
+    assert_eq!(m.r[1], 0x3412);
+}
+
+#[test]
+fn cmp_r_r() {
+    let m = binary(rr(Opcode::CmpRR, 1, 2), 3, 3);
+    assert!(m.flags.z);
+    assert_eq!(m.r[1], 3);
+}
+
+#[test]
+fn cmp_r_imm() {
+    let m = binary(ri(Opcode::CmpRImm, 1, 4), 3, 0);
+    assert!(m.flags.less() && !m.flags.z);
+}
+
+#[test]
+fn cmp_r_mem() {
+    let m = binary(rm(Opcode::CmpRMem, 1, 0x100), 5, 4);
+    assert!(m.flags.greater());
+}
+
+#[test]
+fn cmp_r_ind() {
+    let m = exec(rr(Opcode::CmpRInd, 1, 2), |m| {
+        m.r[1] = 5;
+        m.r[2] = 0x200;
+        m.write_mem(0x200, 5).unwrap();
+    });
+    assert!(m.flags.z);
+}
+
+#[test]
+fn cmp_r_idx() {
+    let m = exec(rx(Opcode::CmpRIdx, 1, 2, 1), |m| {
+        m.r[1] = 5;
+        m.r[2] = 0x200;
+        m.write_mem(0x201, 6).unwrap();
+    });
+    assert!(m.flags.less());
+}
+
+#[test]
+fn be_label() {
+    assert!(branch(Opcode::Be, 3, 3));
+    assert!(!branch(Opcode::Be, 3, 4));
+}
+
+#[test]
+fn bne_label() {
+    assert!(branch(Opcode::Bne, 3, 4));
+    assert!(!branch(Opcode::Bne, 3, 3));
+}
+
+#[test]
+fn bg_label() {
+    assert!(branch(Opcode::Bg, 1, -1));
+    assert!(!branch(Opcode::Bg, 1, 1));
+    assert!(branch(Opcode::Bg, i16::MAX, i16::MIN));
+}
+
+#[test]
+fn bge_label() {
+    assert!(branch(Opcode::Bge, 1, 1));
+    assert!(!branch(Opcode::Bge, -1, 1));
+}
+
+#[test]
+fn bl_label() {
+    assert!(branch(Opcode::Bl, -1, 1));
+    assert!(!branch(Opcode::Bl, 1, 1));
+    assert!(branch(Opcode::Bl, i16::MIN, 1));
+}
+
+#[test]
+fn ble_label() {
+    assert!(branch(Opcode::Ble, 1, 1));
+    assert!(!branch(Opcode::Ble, 2, 1));
+}
+
+#[test]
+fn bc_label() {
+    assert!(branch(Opcode::Bc, 1, -1));
+    assert!(!branch(Opcode::Bc, -1, 1));
+}
+
+#[test]
+fn bnc_label() {
+    assert!(branch(Opcode::Bnc, -1, 1));
+    assert!(!branch(Opcode::Bnc, 1, -1));
+}
+
+#[test]
+fn bn_label() {
+    assert!(branch(Opcode::Bn, 1, 2));
+    assert!(!branch(Opcode::Bn, 2, 1));
+}
+
+#[test]
+fn bnn_label() {
+    assert!(branch(Opcode::Bnn, 2, 1));
+    assert!(!branch(Opcode::Bnn, 1, 2));
+}
+
+#[test]
+fn bv_label() {
+    assert!(branch(Opcode::Bv, i16::MIN, 1));
+    assert!(!branch(Opcode::Bv, 0, 1));
+}
+
+#[test]
+fn bnv_label() {
+    assert!(branch(Opcode::Bnv, 0, 1));
+    assert!(!branch(Opcode::Bnv, i16::MIN, 1));
+}
+
+#[test]
+fn ba_label() {
+    assert!(branch(Opcode::Ba, -1, 1));
+    assert!(!branch(Opcode::Ba, 1, 1));
+    assert!(!branch(Opcode::Ba, 1, -1));
+}
+
+#[test]
+fn bbe_label() {
+    assert!(branch(Opcode::Bbe, 1, 1));
+    assert!(branch(Opcode::Bbe, 1, -1));
+    assert!(!branch(Opcode::Bbe, -1, 1));
+}
+
+#[test]
+fn jmp_label() {
+    let mut code = a(Opcode::Jmp, 7);
+    code.extend(ri(Opcode::CpRImm, 0, 1));
+    let m = exec(code, |_| ());
+    assert_eq!(m.r[0], 0);
+    assert_eq!(m.pc, 7);
+}
+
+#[test]
+fn jmp_r() {
+    let mut code = r(Opcode::JmpR, 1);
+    code.extend(ri(Opcode::CpRImm, 0, 1));
+    let m = exec(code, |m| m.r[1] = 6);
+    assert_eq!(m.r[0], 0);
+    assert_eq!(m.pc, 6);
+    let mut m = machine();
+    m.load_code(&r(Opcode::JmpR, 1)).unwrap();
+    m.r[1] = 100;
+    assert!(m.run().is_err());
+}
+
+#[test]
+fn jmp_mem() {
+    let mut code = a(Opcode::JmpInd, 0x100);
+    code.extend(ri(Opcode::CpRImm, 0, 1));
+    let m = exec(code, |m| m.write_mem(0x100, 7).unwrap());
+    assert_eq!(m.r[0], 0);
+    assert_eq!(m.pc, 7);
+}
+
+#[test]
+fn call_label() {
+    let mut code = a(Opcode::Call, 4);
+    code.push(Opcode::Halt as u8);
+    code.extend(ri(Opcode::CpRImm, 0, 1));
+    code.push(Opcode::Ret as u8);
+    let m = exec(code, |_| ());
+    assert_eq!(m.pc, 3);
+    assert_eq!(m.r[0], 1);
+    assert_eq!(m.r[SP], STACK_TOP as i16);
+    assert!(m.stack.is_empty());
+}
+
+#[test]
+fn call_r() {
+    let mut code = r(Opcode::CallR, 1);
+    code.push(Opcode::Halt as u8);
+    code.push(Opcode::Ret as u8);
+    let m = exec(code, |m| m.r[1] = 3);
+    assert_eq!(m.pc, 2);
+    assert_eq!(m.r[SP], STACK_TOP as i16);
+}
+
+#[test]
+fn ret() {
+    let mut m = machine();
+    m.load_code(&[Opcode::Ret as u8]).unwrap();
+    assert!(m.run().is_err());
+    let m = exec(vec![Opcode::Ret as u8, Opcode::Nop as u8], |m| {
+        m.r[SP] -= word() as i16;
+        m.write_mem(STACK_TOP - word(), 1).unwrap();
+    });
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn calls_label() {
+    let mut code = a(Opcode::CallS, 4);
+    code.push(Opcode::Halt as u8);
+    code.extend(ri(Opcode::CpRImm, 0, 1));
+    code.push(Opcode::RetS as u8);
+    let m = exec(code, |_| ());
+    assert_eq!(m.pc, 3);
+    assert_eq!(m.r[0], 0);
+    assert!(m.stack.is_empty());
+}
+
+#[test]
+fn calls_label_callee_saved() {
+    let mut code = a(Opcode::CallS, 4);
+    code.push(Opcode::Halt as u8);
+    code.extend(ri(Opcode::CpRImm, 0, 1));
+    code.extend(ri(Opcode::CpRImm, 4, 1));
+    code.push(Opcode::RetS as u8);
+    let m = exec(code, |m| m.rets_restores = RetsRestores::CalleeSaved);
+    assert_eq!(m.pc, 3);
+    assert_eq!(m.r[0], 1);
+    assert_eq!(m.r[4], 0);
+}
+
+#[test]
+fn rets() {
+    let mut m = machine();
+    m.load_code(&[Opcode::RetS as u8]).unwrap();
+    assert!(m.run().is_err());
+}
+
+#[test]
+fn push_r() {
+    let m = exec(r(Opcode::Push, 1), |m| m.r[1] = 42);
+    assert_eq!(m.r[SP], (STACK_TOP - word()) as i16);
+    assert_eq!(m.read_mem(STACK_TOP - word()).unwrap(), 42);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn pop_r() {
+    let mut code = r(Opcode::Push, 1);
+    code.extend(r(Opcode::Pop, 2));
+    let m = exec(code, |m| m.r[1] = 42);
+    assert_eq!(m.r[2], 42);
+    assert_eq!(m.r[SP], STACK_TOP as i16);
+    assert_eq!(m.pc, 4);
+    let mut m = machine();
+    m.load_code(&r(Opcode::Pop, 2)).unwrap();
+    assert!(m.run().is_err());
+}
+
+#[test]
+fn puts_label() {
+    let console = BufferConsole::default();
+    let m = exec(a(Opcode::PutS, 0x100), |m| {
+        m.console = Box::new(console.clone());
+        for (i, c) in "ok\n".bytes().enumerate() {
+            m.write_mem(0x100 + i as u16, c as i16).unwrap();
+        }
+    });
+    assert_eq!(console.output_string(), "ok\n");
+    assert_eq!(m.pc, 3);
+}
+
+#[test]
+fn putc_r() {
+    let console = BufferConsole::default();
+    let m = exec(r(Opcode::PutC, 1), |m| {
+        m.console = Box::new(console.clone());
+        m.r[1] = 'x' as i16;
+    });
+    assert_eq!(console.output_string(), "x");
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn putn_r() {
+    let console = BufferConsole::default();
+    let m = exec(r(Opcode::PutN, 1), |m| {
+        m.console = Box::new(console.clone());
+        m.r[1] = -1234;
+    });
+    assert_eq!(console.output_string(), "-1234");
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn getc_r() {
+    let mut code = r(Opcode::GetC, 1);
+    code.extend(r(Opcode::GetC, 2));
+    let m = exec(code, |m| m.console = Box::new(BufferConsole::new(b"a")));
+    assert_eq!(m.r[1], 'a' as i16);
+    assert_eq!(m.r[2], -1);
+    assert_eq!(m.pc, 4);
+}
+
+/// Appends `HALT` and a handler storing 7 to mem[0x100] to `code`,
+/// together with a setup installing the handler for interrupt line `irq`.
+fn with_handler(mut code: Vec<u8>, irq: u8) -> (Vec<u8>, impl FnOnce(&mut Machine)) {
+    code.push(Opcode::Halt as u8);
+    let handler = code.len() as i16;
+    code.extend(ri(Opcode::CpRImm, 3, 7));
+    code.extend(a(Opcode::CpMemR, 0x100));
+    code.push(3);
+    code.push(Opcode::IRet as u8);
+    (code, move |m: &mut Machine| m.write_mem(m.interrupt_vector(irq), handler).unwrap())
+}
+
+#[test]
+fn ei() {
+    let (code, setup) = with_handler(vec![Opcode::Ei as u8], 2);
+    let m = exec(code, |m| {
+        setup(m);
+        m.raise(2).unwrap();
+    });
+    assert_eq!(m.read_mem(0x100).unwrap(), 7);
+    assert_eq!(m.r[3], 0);
+    assert_eq!(m.pc, 1);
+}
+
+#[test]
+fn di() {
+    let m = exec(vec![Opcode::Di as u8], |m| m.interrupts_enabled = true);
+    assert!(!m.interrupts_enabled);
+    assert_eq!(m.pc, 1);
+}
+
+#[test]
+fn iret() {
+    let mut code = vec![Opcode::Ei as u8];
+    code.extend(rr(Opcode::CmpRR, 1, 1));
+    code.push(Opcode::Halt as u8);
+    let handler = code.len() as i16;
+    code.extend(ri(Opcode::CpRImm, 1, -1));
+    code.extend(rr(Opcode::CmpRR, 1, 2));
+    code.push(Opcode::IRet as u8);
+    let m = exec(code, |m| {
+        m.write_mem(m.interrupt_vector(2), handler).unwrap();
+        m.r[1] = 5;
+        m.raise(2).unwrap();
+    });
+    assert_eq!(m.r[1], 5);
+    assert!(m.flags.z);
+    assert!(m.interrupts_enabled);
+    assert_eq!(m.pc, 3);
+}
+
+#[test]
+fn wait() {
+    let mut code = ri(Opcode::CpRImm, 1, 10);
+    code.extend(a(Opcode::CpMemR, TIMER_PERIOD));
+    code.push(1);
+    code.push(Opcode::Ei as u8);
+    code.push(Opcode::Wait as u8);
+    let (code, setup) = with_handler(code, IRQ_TIMER);
+    let m = exec(code, setup);
+    assert_eq!(m.read_mem(0x100).unwrap(), 7);
+    assert_eq!(m.pc, 10);
+}
+
+#[test]
+fn keyboard_interrupt() {
+    let mut code = vec![Opcode::Ei as u8, Opcode::Nop as u8, Opcode::Halt as u8];
+    let handler = code.len() as i16;
+    code.extend(r(Opcode::GetC, 3));
+    code.extend(a(Opcode::CpMemR, 0x100));
+    code.push(3);
+    code.push(Opcode::IRet as u8);
+    let m = exec(code, |m| {
+        m.write_mem(m.interrupt_vector(IRQ_KEYBOARD), handler).unwrap();
+        m.console = Box::new(BufferConsole::new(b"k"));
+    });
+    assert_eq!(m.read_mem(0x100).unwrap(), 'k' as i16);
+    assert_eq!(m.pc, 2);
+}
+
+#[test]
+fn interrupt_without_handler() {
+    let m = exec(vec![Opcode::Ei as u8, Opcode::Nop as u8], |m| m.raise(3).unwrap());
+    assert_eq!(m.pc, 2);
+    assert!(m.stack.is_empty());
+    assert_eq!(m.pending_irqs, 0);
+}
+
+#[test]
+fn interrupt_lines() {
+    let mut m = machine();
+    let lines = m.interrupt_lines();
+    assert_eq!(lines as u16 * word(), 16);
+    assert!(m.raise(lines - 1).is_ok());
+    assert!(m.raise(lines).is_err());
+    assert!(m.raise(16).is_err());
+}
+
+#[test]
+fn wait_with_interrupts_disabled() {
+    let mut m = machine();
+    m.load_code(&[Opcode::Wait as u8]).unwrap();
+    assert!(m.run().is_err());
+}
+
+#[test]
+fn syscall_n() {
+    let mut code = a(Opcode::SysCall, 5);
+    code.push(Opcode::Halt as u8);
+    let handler = code.len() as i16;
+    let m = exec(code, |m| {
+        m.write_mem(m.exception_vector(EXC_SYSCALL), handler).unwrap();
+        m.mode = Mode::User;
+    });
+    assert_eq!(m.r[0], 5);
+    assert_eq!(m.mode, Mode::Supervisor);
+    assert_eq!(m.stack.len(), 1);
+    assert_eq!(m.pc, 4);
+    let mut m = machine();
+    m.load_code(&a(Opcode::SysCall, 5)).unwrap();
+    assert!(m.run().is_err());
+}
+
+#[test]
+fn sysret() {
+    let mut code = a(Opcode::SysCall, 5);
+    code.push(Opcode::Halt as u8);
+    let handler = code.len() as i16;
+    code.extend(ri(Opcode::CpRImm, 1, 42));
+    code.extend(ri(Opcode::CpRImm, 4, 1));
+    code.push(Opcode::SysRet as u8);
+    // HALT is privileged, so step up to the return into user mode.
+    let mut m = machine();
+    m.load_code(&code).unwrap();
+    m.write_mem(m.exception_vector(EXC_SYSCALL), handler).unwrap();
+    m.mode = Mode::User;
+    for _ in 0..4 {
+        m.step().unwrap();
+    }
+    assert_eq!(m.r[0], 5);
+    assert_eq!(m.r[1], 42);
+    assert_eq!(m.r[4], 0);
+    assert_eq!(m.mode, Mode::User);
+    assert!(m.stack.is_empty());
+    assert_eq!(m.pc, 3);
+}
+
+#[test]
+fn jmpu_label() {
+    let mut code = a(Opcode::JmpU, 4);
+    code.push(Opcode::Nop as u8);
+    code.push(Opcode::Halt as u8);
+    let mut m = machine();
+    m.load_code(&code).unwrap();
+    m.step().unwrap();
+    assert_eq!(m.mode, Mode::User);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn hostcall_n() {
+    let m = exec(a(Opcode::HostCall, 9), |m| {
+        m.register_host_fn(9, |m: &mut Machine| {
+            m.r[0] = m.r[1] * 2;
+            Ok(())
+        });
+        m.r[1] = 21;
+    });
+    assert_eq!(m.r[0], 42);
+    assert_eq!(m.pc, 3);
+    let mut m = machine();
+    m.load_code(&a(Opcode::HostCall, 9)).unwrap();
+    assert!(m.run().is_err());
+}
+
+#[test]
+fn yield_() {
+    let m = exec(vec![Opcode::Yield as u8], |_| ());
+    assert!(m.yielded);
+    assert_eq!(m.pc, 1);
+}
+
+#[test]
+fn nop() {
+    let m = exec(vec![Opcode::Nop as u8], |_| ());
+    assert_eq!(m.pc, 1);
+}
+
+#[test]
+fn halt() {
+    let m = exec(vec![], |_| ());
+    assert_eq!(m.pc, 0);
+}
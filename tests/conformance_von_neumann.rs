@@ -0,0 +1,14 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+//! The instruction conformance tests with code and data in one byte-addressed memory.
+
+extern crate riscvm;
+
+use riscvm::memory::MemoryModel;
+
+const MODEL: MemoryModel = MemoryModel::VonNeumann;
+
+include!("conformance/cases.rs");
@@ -0,0 +1,158 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use riscvm::{Machine, MachineBuilder, SP};
+use riscvm::bus::Ram;
+use riscvm::error::Error;
+use riscvm::exception::*;
+use riscvm::opcode::Opcode;
+use riscvm::privilege::Mode;
+
+/// Runs `code` followed by a handler that halts, installed for `cause`.
+fn run_trapping(code: Vec<u8>, cause: u16) -> (Machine, Result<(), Error>) {
+    run_trapping_on(Machine::new(), code, cause)
+}
+
+fn run_trapping_on(mut m: Machine, mut code: Vec<u8>, cause: u16) -> (Machine, Result<(), Error>) {
+    let handler = code.len() as i16;
+    code.push(Opcode::Halt as u8);
+    m.code = code;
+    m.error_policy = ErrorPolicy::Trap;
+    m.write_mem(m.exception_vector(cause), handler).unwrap();
+    let result = m.run();
+    (m, result)
+}
+
+#[test]
+fn division_by_zero_is_fatal_by_default() {
+    let mut m = Machine::new();
+    m.code = vec![Opcode::DivRR as u8, 0x12, Opcode::Halt as u8];
+    assert!(matches!(m.run(), Err(Error::DivisionByZero)));
+}
+
+#[test]
+fn division_by_zero_traps() {
+    let code = vec![Opcode::Nop as u8, Opcode::DivRR as u8, 0x12];
+    let (m, result) = run_trapping(code, EXC_DIVISION_BY_ZERO);
+    assert!(result.is_ok());
+    assert_eq!(m.r[0], EXC_DIVISION_BY_ZERO as i16);
+    assert_eq!(m.r[1], 1);
+    assert_eq!(m.pc, 3);
+}
+
+#[test]
+fn illegal_opcode_traps() {
+    let (m, result) = run_trapping(vec![0x00], EXC_ILLEGAL_OPCODE);
+    assert!(result.is_ok());
+    assert_eq!(m.r[0], EXC_ILLEGAL_OPCODE as i16);
+    assert_eq!(m.r[1], 0);
+}
+
+#[test]
+fn invalid_address_traps() {
+    let mut m = MachineBuilder::new().memory_size(0x8000).build().unwrap();
+    m.attach(EVT_BASE, 0xffff, Rc::new(RefCell::new(Ram::new(EVT_BASE, 0x20))));
+    let (m, result) = run_trapping_on(m, vec![Opcode::CpRMem as u8, 2, 0x00, 0x80], EXC_INVALID_ADDRESS);
+    assert!(result.is_ok());
+    assert_eq!(m.r[0], EXC_INVALID_ADDRESS as i16);
+}
+
+#[test]
+fn stack_underflow_traps() {
+    let (m, result) = run_trapping(vec![Opcode::Ret as u8], EXC_STACK_UNDERFLOW);
+    assert!(result.is_ok());
+    assert_eq!(m.r[0], EXC_STACK_UNDERFLOW as i16);
+}
+
+#[test]
+fn unbounded_recursion_traps() {
+    let (m, result) = run_trapping(vec![Opcode::Call as u8, 0, 0], EXC_STACK_OVERFLOW);
+    assert!(result.is_ok());
+    assert_eq!(m.r[0], EXC_STACK_OVERFLOW as i16);
+    assert_eq!(m.r[SP], 0);
+    assert_eq!(m.timer.borrow().period, 0);
+}
+
+#[test]
+fn recursion_stops_at_the_stack_limit() {
+    let m = MachineBuilder::new().stack_limit(0xee00).build().unwrap();
+    let (m, result) = run_trapping_on(m, vec![Opcode::Call as u8, 0, 0], EXC_STACK_OVERFLOW);
+    assert!(result.is_ok());
+    assert_eq!(m.r[0], EXC_STACK_OVERFLOW as i16);
+    assert_eq!(m.r[SP] as u16, 0xee00);
+    assert_eq!(m.read_mem(0xedff).unwrap(), 0);
+}
+
+#[test]
+fn running_off_the_code_fails() {
+    let mut m = Machine::new();
+    m.code = vec![Opcode::Nop as u8];
+    assert!(matches!(m.run(), Err(Error::InvalidJumpTarget(1, 1))));
+}
+
+#[test]
+fn truncated_instruction_fails() {
+    let mut m = Machine::new();
+    m.code = vec![Opcode::CpRImm as u8, 1];
+    assert!(matches!(m.run(), Err(Error::InvalidJumpTarget(2, 0))));
+}
+
+#[test]
+fn jump_past_the_code_traps() {
+    let (m, result) = run_trapping(vec![Opcode::Jmp as u8, 0x00, 0x10], EXC_INVALID_JUMP_TARGET);
+    assert!(result.is_ok());
+    assert_eq!(m.r[0], EXC_INVALID_JUMP_TARGET as i16);
+    assert_eq!(m.r[1], 0);
+}
+
+#[test]
+fn trap_without_handler_is_fatal() {
+    let (_, result) = run_trapping(vec![0x00], EXC_DIVISION_BY_ZERO);
+    assert!(matches!(result, Err(Error::UnknownOpcode(0x00, 0))));
+}
+
+/// A division by the word at $100 whose handler stores 2 there and returns with `IRET`.
+fn retried_division(mut code: Vec<u8>) -> Machine {
+    let div = code.len();
+    code.extend_from_slice(&[Opcode::DivRMem as u8, 1, 0x00, 0x01]);
+    code.push(Opcode::Nop as u8);
+    let handler = code.len() as i16;
+    code.extend_from_slice(&[Opcode::CpRImm as u8, 2, 2, 0]);
+    code.extend_from_slice(&[Opcode::CpMemR as u8, 0x00, 0x01, 2]);
+    code.push(Opcode::IRet as u8);
+    let mut m = Machine::new();
+    m.code = code;
+    m.error_policy = ErrorPolicy::Trap;
+    m.write_mem(m.exception_vector(EXC_DIVISION_BY_ZERO), handler).unwrap();
+    m.r[0] = 7;
+    m.r[1] = 84;
+    while m.pc != div + 4 {
+        m.step().unwrap();
+    }
+    m
+}
+
+#[test]
+fn iret_retries_the_faulting_instruction() {
+    let m = retried_division(vec![Opcode::Nop as u8]);
+    assert_eq!(m.r[1], 42);
+    assert_eq!(m.r[0], 7);
+    assert_eq!(m.r[2], 0);
+    assert_eq!(m.mode, Mode::Supervisor);
+    assert!(m.stack.is_empty());
+}
+
+#[test]
+fn iret_returns_to_user_mode() {
+    let m = retried_division(vec![Opcode::JmpU as u8, 3, 0]);
+    assert_eq!(m.r[1], 42);
+    assert_eq!(m.mode, Mode::User);
+    assert!(!m.interrupts_enabled);
+}
@@ -0,0 +1,175 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use riscvm::flags::Flags;
+
+/// The flags `CMP a b` leaves behind.
+fn cmp(a: i16, b: i16) -> Flags {
+    Flags::sub(a, b).1
+}
+
+#[test]
+fn add_sets_carry_and_overflow_independently() {
+    let (r, f) = Flags::add(1, 2);
+    assert_eq!(r, 3);
+    assert_eq!(f, Flags::default());
+    let (r, f) = Flags::add(i16::MAX, 1);
+    assert_eq!(r, i16::MIN);
+    assert!(f.v && !f.c && f.n && !f.z);
+    let (r, f) = Flags::add(-1, 1);
+    assert_eq!(r, 0);
+    assert!(f.c && !f.v && f.z && !f.n);
+    let (r, f) = Flags::add(i16::MIN, i16::MIN);
+    assert_eq!(r, 0);
+    assert!(f.c && f.v && f.z);
+}
+
+#[test]
+fn sub_sets_borrow_and_overflow_independently() {
+    let (r, f) = Flags::sub(5, 5);
+    assert_eq!(r, 0);
+    assert!(f.z && !f.c && !f.v && !f.n);
+    let (r, f) = Flags::sub(3, 4);
+    assert_eq!(r, -1);
+    assert!(f.c && f.n && !f.v);
+    let (r, f) = Flags::sub(i16::MIN, 1);
+    assert_eq!(r, i16::MAX);
+    assert!(f.v && !f.c && !f.n);
+    let (r, f) = Flags::sub(i16::MAX, -1);
+    assert_eq!(r, i16::MIN);
+    assert!(f.v && f.c);
+}
+
+#[test]
+fn signed_comparisons() {
+    assert!(cmp(1, -1).greater() && !cmp(1, -1).less());
+    assert!(cmp(-1, 1).less() && !cmp(-1, 1).greater());
+    assert!(!cmp(2, 2).greater() && !cmp(2, 2).less());
+    // The subtraction overflows, the comparison must still be right.
+    assert!(cmp(i16::MAX, i16::MIN).greater());
+    assert!(cmp(i16::MIN, 1).less());
+}
+
+#[test]
+fn unsigned_comparisons() {
+    assert!(cmp(2, 1).above());
+    assert!(!cmp(1, 1).above());
+    // -1 is $FFFF, the largest unsigned value.
+    assert!(cmp(-1, 1).above());
+    assert!(!cmp(1, -1).above());
+}
+
+#[test]
+fn shl_counts() {
+    assert_eq!(Flags::shl(0x1234, 0), (0x1234, Flags::logic(0x1234)));
+    let (r, f) = Flags::shl(3, 15);
+    assert_eq!(r, i16::MIN);
+    assert!(f.c && f.n);
+    let (r, f) = Flags::shl(1, 16);
+    assert_eq!(r, 0);
+    assert!(f.c && f.z);
+    let (r, f) = Flags::shl(-1, 17);
+    assert_eq!(r, 0);
+    assert!(!f.c && f.z);
+    let (r, f) = Flags::shl(-1, -1);
+    assert_eq!(r, 0);
+    assert!(!f.c);
+}
+
+#[test]
+fn shr_counts() {
+    assert_eq!(Flags::shr(-1, 0), (-1, Flags::logic(-1)));
+    let (r, f) = Flags::shr(-1, 15);
+    assert_eq!(r, 1);
+    assert!(f.c);
+    let (r, f) = Flags::shr(i16::MIN, 16);
+    assert_eq!(r, 0);
+    assert!(f.c && f.z);
+    let (r, f) = Flags::shr(-1, 17);
+    assert_eq!(r, 0);
+    assert!(!f.c);
+    let (r, f) = Flags::shr(-1, -1);
+    assert_eq!(r, 0);
+    assert!(!f.c);
+}
+
+#[test]
+fn sar_counts() {
+    assert_eq!(Flags::sar(-4, 0), (-4, Flags::logic(-4)));
+    let (r, f) = Flags::sar(0x4000, 15);
+    assert_eq!(r, 0);
+    assert!(f.c && f.z);
+    let (r, f) = Flags::sar(i16::MIN, 16);
+    assert_eq!(r, -1);
+    assert!(f.c && f.n);
+    let (r, f) = Flags::sar(i16::MAX, 17);
+    assert_eq!(r, 0);
+    assert!(!f.c);
+    let (r, f) = Flags::sar(-2, -1);
+    assert_eq!(r, -1);
+    assert!(f.c);
+}
+
+#[test]
+fn rcl_counts() {
+    // A count of 0, or a multiple of 17, keeps both the value and the carry.
+    let (r, f) = Flags::rcl(0x1234, 0, true);
+    assert_eq!(r, 0x1234);
+    assert!(f.c);
+    let (r, f) = Flags::rcl(1, 15, false);
+    assert_eq!(r, i16::MIN);
+    assert!(!f.c);
+    let (r, f) = Flags::rcl(1, 16, false);
+    assert_eq!(r, 0);
+    assert!(f.c);
+    let (r, f) = Flags::rcl(1, 17, false);
+    assert_eq!(r, 1);
+    assert!(!f.c);
+    // -1 is 65535 = 17 * 3855.
+    let (r, f) = Flags::rcl(0x1234, -1, true);
+    assert_eq!(r, 0x1234);
+    assert!(f.c);
+}
+
+#[test]
+fn rcl_rotates_carry_in() {
+    let (r, f) = Flags::rcl(0, 1, true);
+    assert_eq!(r, 1);
+    assert!(!f.c);
+    let (r, f) = Flags::rcl(i16::MIN, 1, false);
+    assert_eq!(r, 0);
+    assert!(f.c);
+}
+
+#[test]
+fn rcr_counts() {
+    let (r, f) = Flags::rcr(0x1234, 0, true);
+    assert_eq!(r, 0x1234);
+    assert!(f.c);
+    let (r, f) = Flags::rcr(1, 15, false);
+    assert_eq!(r, 4);
+    assert!(!f.c);
+    let (r, f) = Flags::rcr(1, 16, false);
+    assert_eq!(r, 2);
+    assert!(!f.c);
+    let (r, f) = Flags::rcr(1, 17, false);
+    assert_eq!(r, 1);
+    assert!(!f.c);
+    let (r, f) = Flags::rcr(0x1234, -1, true);
+    assert_eq!(r, 0x1234);
+    assert!(f.c);
+}
+
+#[test]
+fn rcr_rotates_carry_in() {
+    let (r, f) = Flags::rcr(0, 1, true);
+    assert_eq!(r, i16::MIN);
+    assert!(!f.c);
+    let (r, f) = Flags::rcr(1, 1, false);
+    assert_eq!(r, 0);
+    assert!(f.c);
+}
@@ -0,0 +1,88 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use riscvm::Machine;
+use riscvm::error::Error;
+use riscvm::exception::*;
+use riscvm::opcode::Opcode;
+
+fn hostcall(id: u16) -> Vec<u8> {
+    let mut code = vec![Opcode::HostCall as u8];
+    code.extend_from_slice(&id.to_le_bytes());
+    code
+}
+
+#[test]
+fn arguments_and_results_in_registers() {
+    let mut m = Machine::new();
+    m.register_host_fn(1, |m: &mut Machine| {
+        m.r[0] = m.r[1] + m.r[2];
+        Ok(())
+    });
+    let mut code = hostcall(1);
+    code.push(Opcode::Halt as u8);
+    m.load_code(&code).unwrap();
+    m.r[1] = 40;
+    m.r[2] = 2;
+    m.run().unwrap();
+    assert_eq!(m.r[0], 42);
+    assert_eq!(m.pc, 3);
+}
+
+#[test]
+fn arguments_in_memory() {
+    let log = Rc::new(RefCell::new(Vec::new()));
+    let mut m = Machine::new();
+    let sink = log.clone();
+    m.register_host_fn(7, move |m: &mut Machine| {
+        let mut addr = m.r[1] as u16;
+        let mut line = String::new();
+        loop {
+            match m.read_mem(addr)? {
+                0 => break,
+                c => line.push(c as u8 as char),
+            }
+            addr += 1;
+        }
+        sink.borrow_mut().push(line);
+        Ok(())
+    });
+    let mut code = hostcall(7);
+    code.extend(hostcall(7));
+    code.push(Opcode::Halt as u8);
+    m.load_code(&code).unwrap();
+    for (i, c) in b"hi\0".iter().enumerate() {
+        m.write_mem(0x100 + i as u16, *c as i16).unwrap();
+    }
+    m.r[1] = 0x100;
+    m.run().unwrap();
+    assert_eq!(*log.borrow(), vec!["hi".to_string(), "hi".to_string()]);
+}
+
+#[test]
+fn unknown_id() {
+    let mut m = Machine::new();
+    m.load_code(&hostcall(3)).unwrap();
+    assert!(matches!(m.run(), Err(Error::UnknownHostFunction(3, 0))));
+}
+
+#[test]
+fn errors_are_trapped() {
+    let mut m = Machine::new();
+    m.register_host_fn(1, |_: &mut Machine| Err(Error::DivisionByZero));
+    let mut code = hostcall(1);
+    code.push(Opcode::Halt as u8);
+    m.load_code(&code).unwrap();
+    m.error_policy = ErrorPolicy::Trap;
+    m.write_mem(m.exception_vector(EXC_DIVISION_BY_ZERO), 3).unwrap();
+    m.run().unwrap();
+    assert_eq!(m.r[0], EXC_DIVISION_BY_ZERO as i16);
+    assert_eq!(m.r[1], 0);
+}
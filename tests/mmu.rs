@@ -0,0 +1,140 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use riscvm::{Machine, MachineBuilder};
+use riscvm::error::Error;
+use riscvm::exception::*;
+use riscvm::mmu::*;
+use riscvm::opcode::Opcode;
+use riscvm::privilege::Mode;
+use riscvm::protection::Access;
+
+const PAGE_TABLE: u16 = 0x1000;
+
+/// A machine running `code` with paging enabled: page 0 and the vector page are identity mapped,
+/// and each of `mappings` maps a virtual page to a page table entry.
+fn paged(code: &[u8], mappings: &[(u16, u16)]) -> Machine {
+    let mut m = MachineBuilder::new().mmu().build().unwrap();
+    m.load_code(code).unwrap();
+    m.write_mem(PAGE_TABLE, (PTE_PRESENT | PTE_USER) as i16).unwrap();
+    m.write_mem(PAGE_TABLE + 0xff, (0xff00 | PTE_PRESENT | PTE_WRITABLE) as i16).unwrap();
+    for &(page, entry) in mappings {
+        m.write_mem(PAGE_TABLE + page, entry as i16).unwrap();
+    }
+    m.write_mem(MMU_PAGE_TABLE, PAGE_TABLE as i16).unwrap();
+    m.write_mem(MMU_CONTROL, 1).unwrap();
+    m
+}
+
+fn ram(m: &Machine, addr: u16) -> i16 {
+    m.ram.as_ref().unwrap().borrow().cells[addr as usize]
+}
+
+fn store(addr: u16) -> Vec<u8> {
+    vec![Opcode::CpMemR as u8, addr as u8, (addr >> 8) as u8, 1, Opcode::Halt as u8]
+}
+
+#[test]
+fn disabled_mmu_changes_nothing() {
+    let mut m = MachineBuilder::new().mmu().build().unwrap();
+    m.load_code(&store(0x0105)).unwrap();
+    m.r[1] = 42;
+    m.run().unwrap();
+    assert_eq!(ram(&m, 0x0105), 42);
+    let mmu = m.mmu.as_ref().unwrap().borrow();
+    assert_eq!((mmu.tlb_hits, mmu.tlb_misses), (0, 0));
+}
+
+#[test]
+fn translates_data_accesses() {
+    let mut m = paged(&store(0x0105), &[(0x01, 0x2000 | PTE_PRESENT | PTE_WRITABLE)]);
+    m.r[1] = 42;
+    m.run().unwrap();
+    assert_eq!(ram(&m, 0x2005), 42);
+    assert_eq!(ram(&m, 0x0105), 0);
+    assert_eq!(m.read_mem(0x0105).unwrap(), 42);
+}
+
+#[test]
+fn counts_tlb_hits_and_misses() {
+    let mut m = paged(&store(0x0105), &[(0x01, 0x2000 | PTE_PRESENT | PTE_WRITABLE)]);
+    m.run().unwrap();
+    let mmu = m.mmu.as_ref().unwrap().borrow();
+    // fetching CP misses, its store misses, fetching HALT hits
+    assert_eq!((mmu.tlb_hits, mmu.tlb_misses), (1, 2));
+}
+
+#[test]
+fn translates_instruction_fetches() {
+    let code = [
+        Opcode::Jmp as u8, 0x04, 0x10,
+        Opcode::Halt as u8,
+        Opcode::CpRImm as u8, 1, 7, 0,
+        Opcode::Halt as u8,
+    ];
+    let mut m = paged(&code, &[(0x10, PTE_PRESENT)]);
+    m.run().unwrap();
+    assert_eq!(m.r[1], 7);
+    assert_eq!(m.pc, 0x1008);
+}
+
+#[test]
+fn not_present_page_faults() {
+    let mut m = paged(&store(0x0105), &[]);
+    assert!(matches!(m.run(), Err(Error::PageFault { addr: 0x0105, pc: 0, kind: Access::Write })));
+    assert_eq!(m.mmu.as_ref().unwrap().borrow().fault_addr, 0x0105);
+}
+
+#[test]
+fn read_only_and_supervisor_pages_fault() {
+    let mut m = paged(&store(0x0105), &[(0x01, 0x2000 | PTE_PRESENT | PTE_USER)]);
+    assert!(matches!(m.run(), Err(Error::PageFault { kind: Access::Write, .. })));
+    let mut m = paged(&store(0x0105), &[(0x01, 0x2000 | PTE_PRESENT | PTE_WRITABLE)]);
+    m.mode = Mode::User;
+    assert!(matches!(m.run(), Err(Error::PageFault { kind: Access::Write, .. })));
+}
+
+#[test]
+fn page_fault_traps() {
+    let mut code = store(0x0105);
+    code.push(Opcode::Halt as u8);
+    let mut m = paged(&code, &[]);
+    m.error_policy = ErrorPolicy::Trap;
+    m.write_mem(m.exception_vector(EXC_INVALID_ADDRESS), 4).unwrap();
+    m.write_mem(m.exception_vector(EXC_PAGE_FAULT), 5).unwrap();
+    m.run().unwrap();
+    assert_eq!(m.r[0], EXC_PAGE_FAULT as i16);
+    assert_eq!(m.r[1], 0);
+    assert_eq!(m.pc, 5);
+    let mmu = m.mmu.as_ref().unwrap().borrow();
+    assert_eq!((mmu.fault_status, mmu.fault_addr), (FAULT_WRITE, 0x0105));
+}
+
+#[test]
+fn other_exceptions_clear_the_fault_status() {
+    let code = [
+        Opcode::CpRMem as u8, 1, 0x05, 0x01,
+        Opcode::DivRR as u8, 0x12,
+        Opcode::Halt as u8,
+    ];
+    let mut m = paged(&code, &[]);
+    m.error_policy = ErrorPolicy::Trap;
+    m.write_mem(m.exception_vector(EXC_PAGE_FAULT), 4).unwrap();
+    m.write_mem(m.exception_vector(EXC_DIVISION_BY_ZERO), 6).unwrap();
+    m.step().unwrap();
+    assert_eq!(m.pc, 4);
+    assert_eq!(m.mmu.as_ref().unwrap().borrow().fault_status, FAULT_READ);
+    m.run().unwrap();
+    assert_eq!(m.r[0], EXC_DIVISION_BY_ZERO as i16);
+    assert_eq!(m.mmu.as_ref().unwrap().borrow().fault_status, FAULT_NONE);
+}
+
+#[test]
+fn truncated_instruction_on_a_mapped_page() {
+    let mut m = paged(&[Opcode::CpRImm as u8, 1], &[]);
+    assert!(matches!(m.run(), Err(Error::InvalidJumpTarget(2, 0))));
+}
@@ -0,0 +1,106 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use riscvm::{Machine, MachineBuilder};
+use riscvm::bank::BANK_SELECT;
+use riscvm::console::BufferConsole;
+use riscvm::error::Error;
+use riscvm::exception::*;
+use riscvm::opcode::Opcode;
+use riscvm::privilege::Mode;
+use riscvm::protection::Access;
+use riscvm::timer::TIMER_PERIOD;
+
+/// A machine running `code` in user mode.
+fn user(code: Vec<u8>) -> Machine {
+    let mut m = Machine::new();
+    m.load_code(&code).unwrap();
+    m.mode = Mode::User;
+    m
+}
+
+#[test]
+fn starts_in_supervisor_mode() {
+    assert_eq!(Machine::new().mode, Mode::Supervisor);
+}
+
+#[test]
+fn privileged_instruction_faults() {
+    for op in [Opcode::Ei, Opcode::Di, Opcode::IRet, Opcode::Wait, Opcode::SysRet, Opcode::HostCall, Opcode::Halt] {
+        let byte = op as u8;
+        let mut m = user(vec![Opcode::Nop as u8, byte]);
+        assert!(matches!(m.run(), Err(Error::PrivilegedInstruction(b, 1)) if b == byte));
+    }
+}
+
+#[test]
+fn device_access_faults() {
+    let mut m = user(vec![Opcode::CpMemR as u8, TIMER_PERIOD as u8, (TIMER_PERIOD >> 8) as u8, 1]);
+    assert!(matches!(m.run(), Err(Error::ProtectionFault { addr: TIMER_PERIOD, pc: 0, kind: Access::Write })));
+    let mut m = MachineBuilder::new().banked_memory(0x8000, 0x100, 4).build().unwrap();
+    m.load_code(&[Opcode::CpRMem as u8, 1, BANK_SELECT as u8, (BANK_SELECT >> 8) as u8]).unwrap();
+    m.mode = Mode::User;
+    assert!(matches!(m.run(), Err(Error::ProtectionFault { addr: BANK_SELECT, pc: 0, kind: Access::Read })));
+}
+
+#[test]
+fn user_mode_keeps_ram_and_stack() {
+    let mut m = user(vec![Opcode::CpMemR as u8, 0x00, 0x01, 1, Opcode::Push as u8, 1, Opcode::Pop as u8, 2]);
+    m.r[1] = 42;
+    for _ in 0..3 {
+        m.step().unwrap();
+    }
+    assert_eq!(m.read_mem(0x100).unwrap(), 42);
+    assert_eq!(m.r[2], 42);
+}
+
+#[test]
+fn fault_traps_into_supervisor_mode() {
+    let mut m = user(vec![Opcode::Ei as u8, Opcode::Halt as u8]);
+    m.error_policy = ErrorPolicy::Trap;
+    m.mode = Mode::Supervisor;
+    m.write_mem(m.exception_vector(EXC_PROTECTION_FAULT), 1).unwrap();
+    m.mode = Mode::User;
+    m.run().unwrap();
+    assert_eq!(m.mode, Mode::Supervisor);
+    assert_eq!(m.r[0], EXC_PROTECTION_FAULT as i16);
+    assert_eq!(m.r[1], 0);
+}
+
+#[test]
+fn kernel_runs_user_program() {
+    let console = BufferConsole::default();
+    let mut m = Machine::new();
+    m.console = Box::new(console.clone());
+    let vector = m.exception_vector(EXC_SYSCALL).to_le_bytes();
+    m.load_code(&[
+        // kernel: install the SYSCALL handler at 21, enter the user program at 11
+        Opcode::CpRImm as u8, 2, 21, 0,
+        Opcode::CpMemR as u8, vector[0], vector[1], 2,
+        Opcode::JmpU as u8, 11, 0,
+        // user program: print 'A' through SYSCALL 1, then exit through SYSCALL 0
+        Opcode::CpRImm as u8, 1, b'A', 0,
+        Opcode::SysCall as u8, 1, 0,
+        Opcode::SysCall as u8, 0, 0,
+        // SYSCALL handler: halt for 0, print r1 otherwise
+        Opcode::CmpRImm as u8, 0, 0, 0,
+        Opcode::Be as u8, 31, 0,
+        Opcode::PutC as u8, 1,
+        Opcode::SysRet as u8,
+        Opcode::Halt as u8,
+    ]).unwrap();
+    m.run().unwrap();
+    assert_eq!(console.output_string(), "A");
+    assert_eq!(m.mode, Mode::Supervisor);
+    assert_eq!(m.pc, 31);
+}
+
+#[test]
+fn unhandled_syscall() {
+    let mut m = user(vec![Opcode::SysCall as u8, 3, 0]);
+    assert!(matches!(m.run(), Err(Error::UnhandledSyscall(3, 0))));
+}
@@ -0,0 +1,68 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use riscvm::MachineBuilder;
+use riscvm::error::Error;
+use riscvm::exception::{ErrorPolicy, EXC_PROTECTION_FAULT};
+use riscvm::opcode::Opcode;
+use riscvm::protection::{Access, Permissions};
+
+#[test]
+fn write_to_read_only_region() {
+    let mut m = MachineBuilder::new().protect(0x1000, 0x1fff, Permissions::R).build().unwrap();
+    m.code = vec![Opcode::CpRImm as u8, 1, 1, 0, Opcode::CpMemR as u8, 0x00, 0x10, 1, Opcode::Halt as u8];
+    assert!(matches!(m.run(), Err(Error::ProtectionFault { addr: 0x1000, pc: 4, kind: Access::Write })));
+    assert_eq!(m.read_mem(0x1000).unwrap(), 0);
+}
+
+#[test]
+fn read_from_inaccessible_region() {
+    let m = MachineBuilder::new().protect(0x1000, 0x1fff, Permissions::NONE).build().unwrap();
+    assert!(matches!(m.read_mem(0x1800), Err(Error::ProtectionFault { kind: Access::Read, .. })));
+    assert!(m.read_mem(0x2000).is_ok());
+}
+
+#[test]
+fn execute_outside_of_executable_region() {
+    let mut m = MachineBuilder::new()
+        .protect(0x0000, 0xffff, Permissions::RW)
+        .protect(0x0000, 0x0001, Permissions::RWX)
+        .build()
+        .unwrap();
+    m.code = vec![Opcode::Nop as u8, Opcode::Nop as u8, Opcode::Nop as u8, Opcode::Halt as u8];
+    assert!(matches!(m.run(), Err(Error::ProtectionFault { addr: 2, pc: 2, kind: Access::Execute })));
+}
+
+#[test]
+fn protection_fault_traps() {
+    let mut m = MachineBuilder::new()
+        .protect(0x1000, 0x1fff, Permissions::R)
+        .error_policy(ErrorPolicy::Trap)
+        .build()
+        .unwrap();
+    m.code = vec![Opcode::CpMemR as u8, 0x00, 0x10, 1, Opcode::Halt as u8];
+    m.write_mem(m.exception_vector(EXC_PROTECTION_FAULT), 4).unwrap();
+    m.run().unwrap();
+    assert_eq!(m.r[0], EXC_PROTECTION_FAULT as i16);
+    assert_eq!(m.pc, 4);
+}
+
+#[test]
+fn self_modifying_code_through_the_code_window() {
+    let mut m = MachineBuilder::new().code_window(0xe000).build().unwrap();
+    // cp r1, #NOP ; cp [$e008], r1 ; <HALT replaced by NOP> ; halt
+    m.code = vec![
+        Opcode::CpRImm as u8, 1, Opcode::Nop as u8, 0,
+        Opcode::CpMemR as u8, 0x08, 0xe0, 1,
+        Opcode::Halt as u8,
+        Opcode::Halt as u8,
+    ];
+    assert_eq!(m.read_mem(0xe000).unwrap(), Opcode::CpRImm as i16);
+    m.run().unwrap();
+    assert_eq!(m.pc, 9);
+    assert_eq!(m.code[8], Opcode::Nop as u8);
+}
@@ -0,0 +1,53 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+mod common;
+
+use riscvm::Machine;
+use riscvm::opcode::Opcode;
+use riscvm::screen::{SCREEN_BASE, SCREEN_CHAR_OUT};
+
+/// Prints the zero-terminated string at $0100 through the character output register.
+fn print_string_program() -> Vec<u8> {
+    let mut code = vec![Opcode::CpRImm as u8, 1];
+    code.extend_from_slice(&0x100i16.to_le_bytes());
+    code.extend_from_slice(&[Opcode::CpRImm as u8, 3]);
+    code.extend_from_slice(&(SCREEN_CHAR_OUT as i16).to_le_bytes());
+    // loop @ 8
+    code.extend_from_slice(&[Opcode::CpRIndInc as u8, 0x21]);
+    code.extend_from_slice(&[Opcode::CmpRImm as u8, 2, 0, 0]);
+    code.extend_from_slice(&[Opcode::Be as u8, 22, 0]);
+    code.extend_from_slice(&[Opcode::CpIndR as u8, 0x32]);
+    code.extend_from_slice(&[Opcode::Jmp as u8, 8, 0]);
+    // done @ 22
+    code.push(Opcode::Halt as u8);
+    code
+}
+
+fn run_with_string(s: &str) -> Machine {
+    let mut m = Machine::new();
+    m.code = print_string_program();
+    for (i, c) in s.bytes().enumerate() {
+        m.write_mem(0x100 + i as u16, c as i16).unwrap();
+    }
+    m.run().expect("program failed");
+    m
+}
+
+#[test]
+fn hello() {
+    let mut m = run_with_string("Hello, Murx!\nsecond line\x08E\n");
+    m.write_mem(SCREEN_BASE + 3 * 80 + 2, '*' as i16).unwrap();
+    common::assert_screen_matches(&m, "hello");
+}
+
+#[test]
+fn scrolling() {
+    let s: String = (0..30).map(|i| format!("line {}\n", i)).collect();
+    let m = run_with_string(&s);
+    common::assert_screen_matches(&m, "scrolling");
+}
@@ -0,0 +1,135 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use riscvm::{Machine, MachineBuilder, IVT_BASE};
+use riscvm::error::Error;
+use riscvm::memory::MemoryModel;
+use riscvm::opcode::Opcode;
+use riscvm::screen::SCREEN_BASE;
+use riscvm::task::{Exit, Scheduler};
+use riscvm::timer::TIMER_PERIOD;
+
+fn ri(op: Opcode, rd: u8, imm: i16) -> Vec<u8> {
+    let mut code = vec![op as u8, rd];
+    code.extend_from_slice(&imm.to_le_bytes());
+    code
+}
+
+fn mr(op: Opcode, addr: u16, rs: u8) -> Vec<u8> {
+    let mut code = vec![op as u8];
+    code.extend_from_slice(&addr.to_le_bytes());
+    code.push(rs);
+    code
+}
+
+/// Prints `c`, yields, prints `c` again and halts with `status` in r0.
+fn chatty(c: u8, status: i16) -> Vec<u8> {
+    let mut code = ri(Opcode::CpRImm, 1, c as i16);
+    code.extend_from_slice(&[Opcode::PutC as u8, 1, Opcode::Yield as u8, Opcode::PutC as u8, 1]);
+    code.extend(ri(Opcode::CpRImm, 0, status));
+    code.push(Opcode::Halt as u8);
+    code
+}
+
+/// Stores `value` to 0x100 and, after yielding, copies 0x100 to r0 and halts.
+fn keeper(value: i16) -> Vec<u8> {
+    let mut code = ri(Opcode::CpRImm, 1, value);
+    code.extend(mr(Opcode::CpMemR, 0x100, 1));
+    code.push(Opcode::Yield as u8);
+    code.extend(ri(Opcode::CpRMem, 0, 0x100));
+    code.push(Opcode::Halt as u8);
+    code
+}
+
+/// Counts r1 up to `n`, then stores `value` to the first screen cell.
+fn counter(n: i16, value: i16) -> Vec<u8> {
+    let mut code = ri(Opcode::AddRImm, 1, 1);
+    code.extend(ri(Opcode::CmpRImm, 1, n));
+    code.extend_from_slice(&[Opcode::Bne as u8, 0, 0]);
+    code.extend(ri(Opcode::CpRImm, 2, value));
+    code.extend(mr(Opcode::CpMemR, SCREEN_BASE, 2));
+    code.push(Opcode::Halt as u8);
+    code
+}
+
+fn run(machine: Machine, objs: &[Vec<u8>], time_slice: Option<usize>) -> Scheduler {
+    let mut scheduler = Scheduler::new(machine);
+    scheduler.time_slice = time_slice;
+    for obj in objs {
+        scheduler.spawn(obj).unwrap();
+    }
+    scheduler.run();
+    scheduler
+}
+
+#[test]
+fn separate_output_and_exit_status() {
+    let s = run(Machine::new(), &[chatty(b'a', 1), chatty(b'b', 2)], None);
+    assert_eq!(s.tasks[0].output(), "aa");
+    assert_eq!(s.tasks[1].output(), "bb");
+    assert!(matches!(s.tasks[0].exit, Some(Exit::Halted(1))));
+    assert!(matches!(s.tasks[1].exit, Some(Exit::Halted(2))));
+}
+
+#[test]
+fn separate_memory() {
+    let s = run(Machine::new(), &[keeper(1), keeper(2)], None);
+    assert!(matches!(s.tasks[0].exit, Some(Exit::Halted(1))));
+    assert!(matches!(s.tasks[1].exit, Some(Exit::Halted(2))));
+    let m = MachineBuilder::new().memory_model(MemoryModel::VonNeumann).build().unwrap();
+    let s = run(m, &[keeper(1), keeper(2)], None);
+    assert!(matches!(s.tasks[0].exit, Some(Exit::Halted(1))));
+    assert!(matches!(s.tasks[1].exit, Some(Exit::Halted(2))));
+}
+
+#[test]
+fn time_slice_preempts() {
+    let s = run(Machine::new(), &[counter(100, 1), counter(1, 2)], None);
+    assert_eq!(s.machine.read_mem(SCREEN_BASE).unwrap(), 2);
+    let s = run(Machine::new(), &[counter(100, 1), counter(1, 2)], Some(10));
+    assert_eq!(s.machine.read_mem(SCREEN_BASE).unwrap(), 1);
+}
+
+#[test]
+fn separate_interrupt_state_and_timer() {
+    // installs a timer handler counting in 0x100, enables interrupts and yields until 3 ticks
+    let mut ticking = ri(Opcode::CpRImm, 1, 30);
+    ticking.extend(mr(Opcode::CpMemR, IVT_BASE, 1));
+    ticking.extend(ri(Opcode::CpRImm, 1, 5));
+    ticking.extend(mr(Opcode::CpMemR, TIMER_PERIOD, 1));
+    ticking.extend_from_slice(&[Opcode::Ei as u8, Opcode::Yield as u8]);
+    ticking.extend(ri(Opcode::CpRMem, 0, 0x100));
+    ticking.extend(ri(Opcode::CmpRImm, 0, 3));
+    ticking.extend_from_slice(&[Opcode::Bne as u8, 17, 0, Opcode::Halt as u8]);
+    ticking.extend(ri(Opcode::CpRMem, 3, 0x100));
+    ticking.extend(ri(Opcode::AddRImm, 3, 1));
+    ticking.extend(mr(Opcode::CpMemR, 0x100, 3));
+    ticking.push(Opcode::IRet as u8);
+    assert_eq!(ticking.len(), 43);
+    // counts to 10 in r0, yielding in between; its timer handler would halt with -1
+    let mut computing = ri(Opcode::CpRImm, 1, 25);
+    computing.extend(mr(Opcode::CpMemR, IVT_BASE, 1));
+    computing.extend(ri(Opcode::CpRImm, 0, 0));
+    computing.push(Opcode::Yield as u8);
+    computing.extend(ri(Opcode::AddRImm, 0, 1));
+    computing.extend(ri(Opcode::CmpRImm, 0, 10));
+    computing.extend_from_slice(&[Opcode::Bne as u8, 12, 0, Opcode::Halt as u8]);
+    computing.extend(ri(Opcode::CpRImm, 0, -1));
+    computing.push(Opcode::Halt as u8);
+    assert_eq!(computing.len(), 30);
+    let s = run(Machine::new(), &[ticking, computing], None);
+    assert!(matches!(s.tasks[0].exit, Some(Exit::Halted(3))));
+    assert!(matches!(s.tasks[1].exit, Some(Exit::Halted(10))));
+}
+
+#[test]
+fn failing_task_does_not_stop_the_others() {
+    let s = run(Machine::new(), &[vec![0x00], chatty(b'b', 2)], None);
+    assert!(matches!(s.tasks[0].exit, Some(Exit::Failed(Error::UnknownOpcode(0x00, 0)))));
+    assert!(matches!(s.tasks[1].exit, Some(Exit::Halted(2))));
+    assert_eq!(s.tasks[1].output(), "bb");
+}
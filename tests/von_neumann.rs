@@ -0,0 +1,63 @@
+/*
+ * Copyright (c) 2022 Oliver Lau <oliver@ersatzworld.net>
+ * All rights reserved.
+ */
+
+extern crate riscvm;
+
+use riscvm::{Machine, MachineBuilder};
+use riscvm::console::BufferConsole;
+use riscvm::error::Error;
+use riscvm::memory::MemoryModel;
+use riscvm::opcode::Opcode;
+
+fn machine() -> Machine {
+    MachineBuilder::new().memory_model(MemoryModel::VonNeumann).build().unwrap()
+}
+
+#[test]
+fn string_table_after_the_code() {
+    let console = BufferConsole::default();
+    let mut m = MachineBuilder::new()
+        .memory_model(MemoryModel::VonNeumann)
+        .console(Box::new(console.clone()))
+        .build()
+        .unwrap();
+    let mut obj = vec![Opcode::PutS as u8, 4, 0, Opcode::Halt as u8];
+    obj.extend_from_slice(b"hello\0");
+    m.load_code(&obj).unwrap();
+    m.run().unwrap();
+    assert_eq!(console.output_string(), "hello");
+}
+
+#[test]
+fn code_bytes_are_little_endian_words() {
+    let mut m = machine();
+    m.load_code(&[Opcode::CpRMem as u8, 1, 0, 0, Opcode::Halt as u8]).unwrap();
+    m.run().unwrap();
+    assert_eq!(m.r[1], (1 << 8) | Opcode::CpRMem as i16);
+}
+
+#[test]
+fn data_writes_modify_the_code() {
+    let mut m = machine();
+    m.load_code(&[Opcode::Halt as u8]).unwrap();
+    m.write_mem(0x0000, Opcode::Nop as i16 | (Opcode::Halt as i16) << 8).unwrap();
+    m.run().unwrap();
+    assert_eq!(m.pc, 1);
+}
+
+#[test]
+fn word_at_the_last_address() {
+    let mut m = machine();
+    m.load_code(&[Opcode::CpRMem as u8, 1, 0xff, 0xff, Opcode::Halt as u8]).unwrap();
+    assert!(matches!(m.run(), Err(Error::InvalidAddress(0xffff, 0))));
+    assert!(m.write_mem(0xffff, 1).is_err());
+    assert_eq!(m.code[0], Opcode::CpRMem as u8);
+}
+
+#[test]
+fn object_larger_than_memory() {
+    let mut m = MachineBuilder::new().memory_model(MemoryModel::VonNeumann).memory_size(4).build().unwrap();
+    assert!(matches!(m.load_code(&[0; 5]), Err(Error::ObjectFileTooLarge(5))));
+}